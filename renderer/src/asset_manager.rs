@@ -10,8 +10,13 @@ use collections::SparseMap;
 use maths::linear::Mat4f;
 
 use crate::{
-    model::{load_obj, Mesh, MeshInstance, Model, ModelInstance},
-    texture::Texture,
+    light::Light,
+    material::Material,
+    model::{
+        load_obj, AlphaMode, LightingMode, Mesh, MeshInstance, Model, ModelInstance, ShadingMode,
+    },
+    shader::Shader,
+    texture::{Cubemap, Texture},
     util::file_name,
 };
 
@@ -39,6 +44,12 @@ impl<T> Clone for AssetId<T> {
     }
 }
 
+impl<T> fmt::Debug for AssetId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AssetId").field(&self.inner).finish()
+    }
+}
+
 pub trait Named {
     fn name(&self) -> &str;
 }
@@ -125,6 +136,10 @@ pub struct AssetManager {
     pub(crate) models: AssetStore<Model>,
     pub(crate) meshes: AssetStore<Mesh>,
     pub(crate) textures: AssetStore<Texture>,
+    pub(crate) cubemaps: AssetStore<Cubemap>,
+    pub(crate) shaders: AssetStore<Shader>,
+    pub(crate) lights: AssetStore<Light>,
+    pub(crate) materials: AssetStore<Material>,
 }
 
 impl AssetManager {
@@ -133,9 +148,113 @@ impl AssetManager {
             models: AssetStore::new(),
             meshes: AssetStore::new(),
             textures: AssetStore::new(),
+            cubemaps: AssetStore::new(),
+            shaders: AssetStore::new(),
+            lights: AssetStore::new(),
+            materials: AssetStore::new(),
         }
     }
 
+    pub fn insert_mesh(&mut self, mesh: Mesh) -> AssetId<Mesh> {
+        self.meshes.insert(mesh)
+    }
+
+    pub fn set_mesh_shading(&mut self, mesh_id: AssetId<Mesh>, shading: ShadingMode) {
+        let mesh = self.meshes.get_mut(mesh_id).unwrap();
+        mesh.shading = shading;
+    }
+
+    pub fn set_mesh_alpha_mode(&mut self, mesh_id: AssetId<Mesh>, alpha_mode: AlphaMode) {
+        let mesh = self.meshes.get_mut(mesh_id).unwrap();
+        mesh.alpha_mode = alpha_mode;
+    }
+
+    pub fn set_mesh_lighting(&mut self, mesh_id: AssetId<Mesh>, lighting: LightingMode) {
+        let mesh = self.meshes.get_mut(mesh_id).unwrap();
+        mesh.lighting = lighting;
+    }
+
+    pub fn insert_material(&mut self, material: Material) -> AssetId<Material> {
+        self.materials.insert(material)
+    }
+
+    pub fn material_id(&self, name: &str) -> Option<AssetId<Material>> {
+        self.materials.get_id(name)
+    }
+
+    pub fn material_mut(&mut self, material_id: AssetId<Material>) -> Option<&mut Material> {
+        self.materials.get_mut(material_id)
+    }
+
+    /// Sets the material of a mesh, or `None` for the default material. A mesh still using the
+    /// default shading mode for its previous material switches to the default for the new one, so
+    /// that textured materials are shown, while shading modes set explicitly are kept
+    pub fn set_mesh_material(
+        &mut self,
+        mesh_id: AssetId<Mesh>,
+        material_id: Option<AssetId<Material>>,
+    ) {
+        let materials = &self.materials;
+        let default_shading = |material_id: Option<AssetId<Material>>| {
+            let texture_id = material_id
+                .and_then(|id| materials.get(id))
+                .and_then(|material| material.diffuse_texture);
+            ShadingMode::default_for(texture_id)
+        };
+
+        let mesh = self.meshes.get_mut(mesh_id).unwrap();
+        if mesh.shading == default_shading(mesh.material_id) {
+            mesh.shading = default_shading(material_id);
+        }
+        mesh.material_id = material_id;
+    }
+
+    pub fn insert_light(&mut self, light: Light) -> AssetId<Light> {
+        self.lights.insert(light)
+    }
+
+    pub fn light_mut(&mut self, light_id: AssetId<Light>) -> Option<&mut Light> {
+        self.lights.get_mut(light_id)
+    }
+
+    pub fn remove_light(&mut self, light_id: AssetId<Light>) -> Option<Light> {
+        self.lights.remove(light_id)
+    }
+
+    pub fn insert_shader(&mut self, shader: Shader) -> AssetId<Shader> {
+        self.shaders.insert(shader)
+    }
+
+    /// Sets the custom shaders used by a mesh, or `None` to only use the built in shading
+    pub fn set_mesh_shader(&mut self, mesh_id: AssetId<Mesh>, shader_id: Option<AssetId<Shader>>) {
+        let mesh = self.meshes.get_mut(mesh_id).unwrap();
+        mesh.shader_id = shader_id;
+    }
+
+    pub fn insert_texture(&mut self, texture: Texture) -> AssetId<Texture> {
+        self.textures.insert(texture)
+    }
+
+    pub fn texture_id(&self, name: &str) -> Option<AssetId<Texture>> {
+        self.textures.get_id(name)
+    }
+
+    pub fn texture_mut(&mut self, texture_id: AssetId<Texture>) -> Option<&mut Texture> {
+        self.textures.get_mut(texture_id)
+    }
+
+    pub fn insert_cubemap(&mut self, cubemap: Cubemap) -> AssetId<Cubemap> {
+        self.cubemaps.insert(cubemap)
+    }
+
+    pub fn cubemap_id(&self, name: &str) -> Option<AssetId<Cubemap>> {
+        self.cubemaps.get_id(name)
+    }
+
+    pub fn cubemap_mut(&mut self, cubemap_id: AssetId<Cubemap>) -> Option<&mut Cubemap> {
+        self.cubemaps.get_mut(cubemap_id)
+    }
+
     pub fn spawn_mesh_instance(
         &mut self,
         mesh_id: AssetId<Mesh>,
@@ -192,14 +311,40 @@ impl AssetManager {
             }
         }
 
+        let texture_id = |index: Option<usize>| index.map(|i| texture_ids[i]);
+        let mut material_ids = Vec::with_capacity(obj.materials.len());
+        for obj_material in obj.materials {
+            if let Some(id) = self.materials.get_id(obj_material.material.name()) {
+                material_ids.push(id);
+                continue;
+            }
+
+            let mut material = obj_material.material;
+            material.diffuse_texture = texture_id(obj_material.diffuse_texture);
+            material.ambient_texture = texture_id(obj_material.ambient_texture);
+            material.specular_texture = texture_id(obj_material.specular_texture);
+            material.normal_texture = texture_id(obj_material.normal_texture);
+            material_ids.push(self.materials.insert(material));
+        }
+
         let mut mesh_ids = Vec::with_capacity(obj.meshes.len());
-        for (mut mesh, texture_index) in obj.meshes {
+        for (mesh, material_index) in obj.meshes {
             if self.meshes.contains_name(mesh.name()) {
                 continue;
             };
 
-            mesh.texture_id = texture_index.map(|i| texture_ids[i]);
             let mesh_id = self.meshes.insert(mesh);
+            let material_id = material_index.map(|i| material_ids[i]);
+            self.set_mesh_material(mesh_id, material_id);
+
+            // partially transparent materials are blended with whatever is behind them
+            if material_id
+                .and_then(|id| self.materials.get(id))
+                .is_some_and(|material| material.opacity < 1.0)
+            {
+                self.set_mesh_alpha_mode(mesh_id, AlphaMode::Blend);
+            }
+
             mesh_ids.push(mesh_id);
         }
 
@@ -1,4 +1,4 @@
-use maths::linear::{Mat4f, Vec3f};
+use maths::linear::{Mat4f, Vec2f, Vec3f};
 
 const UP: Vec3f = {
     Vec3f {
@@ -32,11 +32,16 @@ impl Camera {
         &self.view_transform
     }
 
+    /// Transforms points from view space back into world space
+    pub fn inverse_view_transform(&self) -> Mat4f {
+        Self::look_at_inverted(&self.position, &self.direction, &UP)
+    }
+
     pub fn update_view(&mut self) {
         self.view_transform = Self::look_at(&self.position, &self.direction, &UP);
     }
 
-    fn look_at(position: &Vec3f, direction: &Vec3f, up: &Vec3f) -> Mat4f {
+    pub(crate) fn look_at(position: &Vec3f, direction: &Vec3f, up: &Vec3f) -> Mat4f {
         let p = position;
         let d = direction;
         let r = up.cross(*d).normalise();
@@ -89,3 +94,94 @@ impl Camera {
         matrix
     }
 }
+
+/// Maps view space onto the pixels of a render target
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    /// Points are scaled by the focal length divided by their depth, so that distant objects appear
+    /// smaller
+    Perspective { focal_width: f32, focal_height: f32 },
+    /// Points are scaled by a fixed number of pixels per unit, regardless of depth. Only depth is
+    /// interpolated correctly across triangles, which is all that's needed for shadow maps
+    Orthographic { scale: f32 },
+}
+
+/// A view transform and projection, along with the size of the target they project onto
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    /// Transforms points from world space into view space
+    pub transform: Mat4f,
+    pub projection: Projection,
+    pub half_width: f32,
+    pub half_height: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl View {
+    /// The per vertex value that the rasteriser interpolates linearly across the screen, and takes
+    /// the reciprocal of to get each sample's depth
+    #[inline(always)]
+    pub fn depth_inv(&self, depth: f32) -> f32 {
+        match self.projection {
+            Projection::Perspective { .. } => 1.0 / depth,
+            // Depth itself is linear in screen space, so this is offset such that the reciprocal
+            // still increases with depth and stays positive
+            Projection::Orthographic { .. } => self.far + 1.0 - depth,
+        }
+    }
+
+    /// Converts a depth written by the rasteriser back into view space depth
+    #[inline(always)]
+    pub fn linear_depth(&self, depth: f32) -> f32 {
+        match self.projection {
+            Projection::Perspective { .. } => depth,
+            Projection::Orthographic { .. } => self.far + 1.0 - 1.0 / depth,
+        }
+    }
+
+    /// Projects a point in view space onto the target, in pixels, given its `depth_inv`
+    #[inline(always)]
+    pub fn project(&self, point: Vec3f, depth_inv: f32) -> Vec2f {
+        match self.projection {
+            Projection::Perspective {
+                focal_width,
+                focal_height,
+            } => Vec2f::new(
+                (focal_width * point.x) * depth_inv + self.half_width,
+                (-focal_height * point.y) * depth_inv + self.half_height,
+            ),
+            Projection::Orthographic { scale } => Vec2f::new(
+                scale * point.x + self.half_width,
+                -scale * point.y + self.half_height,
+            ),
+        }
+    }
+
+    /// The approximate angle between the rays through neighbouring pixels, in radians. This is
+    /// zero for an orthographic projection, where the rays are parallel
+    #[inline(always)]
+    pub fn pixel_angle(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective { focal_width, .. } => 1.0 / focal_width,
+            Projection::Orthographic { .. } => 0.0,
+        }
+    }
+
+    /// The direction in view space through a point on the target, in pixels, which is the
+    /// reverse of [View::project] for points at a depth of 1.0
+    #[inline(always)]
+    pub fn ray(&self, point: Vec2f) -> Vec3f {
+        match self.projection {
+            Projection::Perspective {
+                focal_width,
+                focal_height,
+            } => Vec3f::new(
+                (point.x - self.half_width) / focal_width,
+                -(point.y - self.half_height) / focal_height,
+                1.0,
+            ),
+            Projection::Orthographic { .. } => Vec3f::new(0.0, 0.0, 1.0),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use maths::linear::{Vec3f, Vec4f};
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct RGB {
     pub r: f32,
     pub g: f32,
@@ -78,6 +78,31 @@ impl RGB {
             b: self.b * alpha + other.b * inv_alpha,
         }
     }
+
+    /// Multiplies each channel by the corresponding channel of the other colour
+    pub fn modulate(self, other: RGB) -> Self {
+        Self {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+        }
+    }
+
+    /// Applies a function to each channel
+    #[inline(always)]
+    pub fn map(self, f: impl Fn(f32) -> f32) -> Self {
+        Self {
+            r: f(self.r),
+            g: f(self.g),
+            b: f(self.b),
+        }
+    }
+
+    /// Perceived brightness, weighting each channel by the Rec. 709 coefficients
+    #[inline(always)]
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
 }
 
 impl From<Vec3f> for RGB {
@@ -97,7 +122,7 @@ impl From<RGBA> for RGB {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct RGBA {
     pub r: f32,
     pub g: f32,
@@ -176,6 +201,26 @@ impl RGBA {
             a: alpha,
         }
     }
+
+    pub fn blend(self, other: RGBA, alpha: f32) -> Self {
+        let inv_alpha = 1.0 - alpha;
+        Self {
+            r: self.r * alpha + other.r * inv_alpha,
+            g: self.g * alpha + other.g * inv_alpha,
+            b: self.b * alpha + other.b * inv_alpha,
+            a: self.a * alpha + other.a * inv_alpha,
+        }
+    }
+
+    /// Multiplies each channel by the corresponding channel of the other colour
+    pub fn modulate(self, other: RGBA) -> Self {
+        Self {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+            a: self.a * other.a,
+        }
+    }
 }
 
 impl From<Vec4f> for RGBA {
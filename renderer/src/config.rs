@@ -0,0 +1,308 @@
+use maths::linear::Vec2f;
+
+use crate::colour::RGB;
+
+/// Runtime configuration for the renderer. This can be passed to [Renderer::new](crate::Renderer::new)
+/// and changed between frames with [Renderer::set_config](crate::Renderer::set_config).
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderConfig {
+    /// The number of worker threads used to rasterise tiles. A value of 0 lets the thread pool
+    /// decide, based on the number of available cores, and a value of 1 switches off threading so
+    /// everything runs on the calling thread
+    pub threads: usize,
+    /// Scale applied to the viewport dimensions to get the internal framebuffer resolution
+    pub res_scale: f32,
+    /// Width and height of the tiles that the framebuffer is split into, in pixels. Sizes of 0 are
+    /// raised to 1
+    pub tile_width: usize,
+    pub tile_height: usize,
+    pub msaa: Msaa,
+
+    /*
+      Camera
+    */
+    pub near: f32,
+    pub far: f32,
+
+    /*
+      Textures
+    */
+    /// The maximum number of mip levels to sample from, where level 0 is the original size and
+    /// subsequent levels are half the size of the previous level
+    pub mip_levels: usize,
+    /// Uses a bitwise AND operation to wrap texture coordinates, for textures with power of two
+    /// dimensions on both axes. This provides a performance boost when sampling textures
+    pub dim_pow_2: bool,
+
+    /*
+      Lighting
+    */
+    /// The maximum number of lights used for per pixel lighting each frame. When there are more,
+    /// the light casting shadows is kept first, then ambient and directional lights, followed by
+    /// those nearest the camera
+    pub max_lights: usize,
+
+    /*
+      Shadows
+    */
+    /// Width and height of the shadow map drawn from the shadow casting light, in texels
+    pub shadow_map_size: usize,
+    /// How far in front of the camera shadows are cast. The shadow map is spread over this
+    /// distance, so larger values give blockier shadows
+    pub shadow_distance: f32,
+    /// Offset towards the light applied to surfaces before comparing them against the shadow map,
+    /// which stops surfaces from shadowing themselves
+    pub shadow_bias: f32,
+    /// Softens the edges of shadows with percentage closer filtering, averaging the shadow test
+    /// over neighbouring texels
+    pub shadow_pcf: bool,
+
+    /*
+      Fog
+    */
+    pub fog: Fog,
+    /// Colour that distant surfaces fade into, or `None` to use the clear colour so that they fade
+    /// into the background. The skybox isn't sampled, so with a skybox this should be set to
+    /// roughly match its colour near the horizon
+    pub fog_colour: Option<RGB>,
+
+    /*
+      Debug
+    */
+    pub debug_tiles: bool,
+}
+
+impl RenderConfig {
+    /// Reciprocal of the depth range, used to map linear depth values into the range 0.0 to 1.0
+    pub fn map_depth_range(&self) -> f32 {
+        1.0 / (self.far - self.near)
+    }
+
+    /// Raises the tile size to at least one pixel on each axis, as empty tiles can't cover the
+    /// framebuffer
+    pub(crate) fn clamp_tile_size(&mut self) {
+        self.tile_width = self.tile_width.max(1);
+        self.tile_height = self.tile_height.max(1);
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            res_scale: 1.0 / 2.0,
+            tile_width: 8,
+            tile_height: 8,
+            msaa: Msaa::Off,
+
+            near: 0.1,
+            far: 256.0,
+
+            mip_levels: 8,
+            dim_pow_2: false,
+
+            max_lights: 8,
+
+            shadow_map_size: 1024,
+            shadow_distance: 32.0,
+            shadow_bias: 0.05,
+            shadow_pcf: true,
+
+            fog: Fog::Off,
+            fog_colour: None,
+
+            debug_tiles: false,
+        }
+    }
+}
+
+/// Multisample anti-aliasing mode. Coverage and depth are tested at several sample positions within
+/// each pixel, while the pixel is only shaded once, and the samples are averaged at the end of the
+/// frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Msaa {
+    #[default]
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub const MAX_SAMPLES: usize = 8;
+
+    pub fn samples(self) -> usize {
+        match self {
+            Msaa::Off => 1,
+            Msaa::X2 => 2,
+            Msaa::X4 => 4,
+            Msaa::X8 => 8,
+        }
+    }
+
+    /// Sample positions relative to the pixel centre, in pixels. These follow the standard Direct3D
+    /// patterns, which lie on a 1/16th pixel grid
+    pub fn sample_positions(self) -> &'static [Vec2f] {
+        const fn pos(x: f32, y: f32) -> Vec2f {
+            Vec2f { x, y }
+        }
+
+        const OFF: [Vec2f; 1] = [pos(0.0, 0.0)];
+        const X2: [Vec2f; 2] = [pos(0.25, 0.25), pos(-0.25, -0.25)];
+        const X4: [Vec2f; 4] = [
+            pos(-0.125, -0.375),
+            pos(0.375, -0.125),
+            pos(-0.375, 0.125),
+            pos(0.125, 0.375),
+        ];
+        const X8: [Vec2f; 8] = [
+            pos(0.0625, -0.1875),
+            pos(-0.0625, 0.1875),
+            pos(0.3125, 0.0625),
+            pos(-0.1875, -0.3125),
+            pos(-0.3125, 0.3125),
+            pos(-0.4375, -0.0625),
+            pos(0.1875, 0.4375),
+            pos(0.4375, -0.4375),
+        ];
+
+        match self {
+            Msaa::Off => &OFF,
+            Msaa::X2 => &X2,
+            Msaa::X4 => &X4,
+            Msaa::X8 => &X8,
+        }
+    }
+}
+
+/// Blends surfaces towards the fog colour with distance from the camera. Distances are normalised,
+/// from 0.0 at the near plane to 1.0 at the far plane. Fog always fades into a single colour,
+/// [RenderConfig::fog_colour] or else the clear colour, even when a skybox is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fog {
+    #[default]
+    Off,
+    /// Increases linearly from nothing at `start` to solid fog at `end`. With an `end` of 1.0,
+    /// surfaces fade out completely before they're clipped by the far plane
+    Linear { start: f32, end: f32 },
+    /// Thickens quickly close to the camera, then more slowly with distance
+    Exponential { density: f32 },
+    /// Stays thin close to the camera, then thickens more quickly than [Fog::Exponential]
+    ExponentialSquared { density: f32 },
+}
+
+impl Fog {
+    /// The amount of fog covering a surface at a normalised distance, from 0.0 for none to 1.0
+    /// for solid fog
+    #[inline(always)]
+    pub fn factor(self, distance: f32) -> f32 {
+        let factor = match self {
+            Fog::Off => return 0.0,
+            Fog::Linear { start, end } => (distance - start) / (end - start).max(f32::EPSILON),
+            Fog::Exponential { density } => 1.0 - (-density * distance).exp(),
+            Fog::ExponentialSquared { density } => 1.0 - (-(density * distance).powi(2)).exp(),
+        };
+
+        factor.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::Vec3f;
+
+    use crate::{
+        colour::{RGB, RGBA},
+        model::ShadingMode,
+        shapes::unit_cube_mesh,
+        testing::{assert_near, centre, render, scene, test_config},
+        texture::{Cubemap, Texture},
+    };
+
+    use super::{Fog, RenderConfig};
+
+    #[test]
+    fn distance_fog() {
+        // the front of the cube is at a depth of 2.5, half way between the near and far planes
+        let render_with = |fog: Fog, fog_colour: Option<RGB>| {
+            let config = RenderConfig {
+                near: 0.5,
+                far: 4.5,
+                fog,
+                fog_colour,
+                ..test_config()
+            };
+            let (mut renderer, cube) = scene(config, unit_cube_mesh(), Vec3f::new(0.0, 0.0, 3.0));
+            renderer.set_clear_colour(RGB::BLUE);
+            renderer
+                .assets_mut()
+                .set_mesh_shading(cube, ShadingMode::Flat(RGB::RED));
+
+            centre(&render(&mut renderer))
+        };
+
+        assert_near(render_with(Fog::Off, None), RGB::RED, 1e-4);
+
+        // fades into the clear colour by default
+        let linear = Fog::Linear {
+            start: 0.0,
+            end: 1.0,
+        };
+        assert_near(render_with(linear, None), RGB::new(0.5, 0.0, 0.5), 1e-4);
+        assert_near(
+            render_with(linear, Some(RGB::WHITE)),
+            RGB::new(1.0, 0.5, 0.5),
+            1e-4,
+        );
+
+        let thick = Fog::Linear {
+            start: 0.0,
+            end: 0.25,
+        };
+        assert_near(render_with(thick, None), RGB::BLUE, 1e-4);
+
+        let factor = 1.0 - (-0.5f32).exp();
+        assert_near(
+            render_with(Fog::Exponential { density: 1.0 }, None),
+            RGB::new(1.0 - factor, 0.0, factor),
+            1e-4,
+        );
+        let factor = 1.0 - (-0.25f32).exp();
+        assert_near(
+            render_with(Fog::ExponentialSquared { density: 1.0 }, None),
+            RGB::new(1.0 - factor, 0.0, factor),
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn fog_ignores_skybox() {
+        let config = RenderConfig {
+            near: 0.5,
+            far: 4.5,
+            fog: Fog::Linear {
+                start: 0.0,
+                end: 1.0,
+            },
+            ..test_config()
+        };
+        let (mut renderer, cube) = scene(config, unit_cube_mesh(), Vec3f::new(0.0, 0.0, 3.0));
+        renderer.set_clear_colour(RGB::BLUE);
+        renderer
+            .assets_mut()
+            .set_mesh_shading(cube, ShadingMode::Flat(RGB::RED));
+
+        let faces = [(); 6].map(|_| Texture::from_pixels("face".into(), 1, 1, vec![RGBA::WHITE]));
+        let cubemap = renderer
+            .assets_mut()
+            .insert_cubemap(Cubemap::new("sky".into(), faces));
+        renderer.set_skybox(Some(cubemap));
+
+        // the cube still fades into the clear colour rather than the white sky behind it
+        assert_near(
+            centre(&render(&mut renderer)),
+            RGB::new(0.5, 0.0, 0.5),
+            1e-4,
+        );
+    }
+}
@@ -6,15 +6,25 @@ pub struct Framebuffer {
     half_width: f32,
     half_height: f32,
     aspect_ratio: f32,
+    samples: usize,
+    /// Depth of each sample, with the samples of each pixel stored contiguously
     depth: Vec<f32>,
     pixels: Vec<RGB>,
+    /// Colour of each sample, laid out like `depth`. Unused without multisampling, in which case
+    /// the pixels are drawn to directly
+    sample_pixels: Vec<RGB>,
 }
 
 impl Framebuffer {
-    pub fn new(width: usize, height: usize) -> Self {
+    pub fn new(width: usize, height: usize, samples: usize) -> Self {
         let len = width * height;
-        let depth = vec![f32::MAX; len];
+        let depth = vec![f32::MAX; len * samples];
         let pixels = vec![RGB::default(); len];
+        let sample_pixels = if samples > 1 {
+            vec![RGB::default(); len * samples]
+        } else {
+            Vec::new()
+        };
 
         Self {
             width,
@@ -22,8 +32,26 @@ impl Framebuffer {
             half_width: width as f32 / 2.0,
             half_height: height as f32 / 2.0,
             aspect_ratio: width as f32 / height as f32,
+            samples,
             depth,
             pixels,
+            sample_pixels,
+        }
+    }
+
+    /// Creates a framebuffer with a single depth sample per pixel and no colour, for passes that
+    /// only draw depth
+    pub fn depth_only(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            half_width: width as f32 / 2.0,
+            half_height: height as f32 / 2.0,
+            aspect_ratio: width as f32 / height as f32,
+            samples: 1,
+            depth: vec![f32::MAX; width * height],
+            pixels: Vec::new(),
+            sample_pixels: Vec::new(),
         }
     }
 
@@ -47,6 +75,11 @@ impl Framebuffer {
         self.aspect_ratio
     }
 
+    /// Number of samples per pixel
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
     pub fn depth(&self) -> &[f32] {
         &self.depth
     }
@@ -63,11 +96,60 @@ impl Framebuffer {
         &mut self.pixels
     }
 
+    /// The colour of each sample, which are the pixels themselves without multisampling
+    pub fn sample_pixels(&self) -> &[RGB] {
+        if self.samples > 1 {
+            &self.sample_pixels
+        } else {
+            &self.pixels
+        }
+    }
+
+    /// Mutable access to the colour and depth of each sample at the same time
+    pub fn samples_mut(&mut self) -> (&mut [RGB], &mut [f32]) {
+        if self.samples > 1 {
+            (&mut self.sample_pixels, &mut self.depth)
+        } else {
+            (&mut self.pixels, &mut self.depth)
+        }
+    }
+
+    /// Averages the samples of each pixel into its final colour. Does nothing without multisampling
+    pub fn resolve(&mut self) {
+        if self.samples == 1 {
+            return;
+        }
+
+        let scale = 1.0 / self.samples as f32;
+
+        for (pixel, samples) in self
+            .pixels
+            .iter_mut()
+            .zip(self.sample_pixels.chunks_exact(self.samples))
+        {
+            let (mut r, mut g, mut b) = (0.0, 0.0, 0.0);
+            for sample in samples {
+                r += sample.r;
+                g += sample.g;
+                b += sample.b;
+            }
+
+            *pixel = RGB::new(r * scale, g * scale, b * scale);
+        }
+    }
+
+    /// Swaps the resolved pixels with another buffer of the same size
+    pub fn swap_pixels(&mut self, pixels: &mut Vec<RGB>) {
+        assert_eq!(pixels.len(), self.pixels.len(), "Wrong number of pixels");
+        std::mem::swap(&mut self.pixels, pixels);
+    }
+
     pub fn clear_depth_buffer(&mut self) {
         self.depth.fill(f32::MAX);
     }
 
     pub fn clear_colour_buffer(&mut self, colour: RGB) {
         self.pixels.fill(colour);
+        self.sample_pixels.fill(colour);
     }
 }
@@ -0,0 +1,531 @@
+use maths::linear::{Mat4f, Vec3f};
+
+use crate::{
+    asset_manager::Named,
+    colour::RGB,
+    model::{transform_normal, transform_point},
+    shadow::ShadowMap,
+};
+
+/// A directional light along with ambient light, which is evaluated at each vertex and
+/// interpolated across triangles, for meshes using [LightingMode::Vertex](crate::LightingMode::Vertex)
+#[derive(Clone, Copy, Debug)]
+pub struct Lighting {
+    /// Unit length direction the light travels in, in world space
+    direction: Vec3f,
+    pub colour: RGB,
+    /// Light reaching every surface, regardless of the direction it faces
+    pub ambient: RGB,
+}
+
+impl Lighting {
+    pub fn new(direction: Vec3f, colour: RGB, ambient: RGB) -> Self {
+        Self {
+            direction: direction.normalise(),
+            colour,
+            ambient,
+        }
+    }
+
+    pub fn direction(&self) -> Vec3f {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Vec3f) {
+        self.direction = direction.normalise();
+    }
+
+    /// Lights a vertex colour, given the vertex normal in world space, using the Lambertian diffuse
+    /// term plus ambient
+    pub fn light_vertex(&self, colour: Vec3f, normal: Vec3f) -> Vec3f {
+        let diffuse = (-self.direction.dot(normal)).max(0.0);
+
+        Vec3f::new(
+            colour.x * (self.ambient.r + self.colour.r * diffuse),
+            colour.y * (self.ambient.g + self.colour.g * diffuse),
+            colour.z * (self.ambient.b + self.colour.b * diffuse),
+        )
+    }
+}
+
+/// Falloff of a light's intensity with distance `d`, as `1 / (constant + linear * d + quadratic * d^2)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Self {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    #[inline(always)]
+    fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+    }
+}
+
+/// Determines where the light from a [Light] comes from. Positions and directions are in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Light reaching every surface equally, regardless of where it is or which way it faces
+    Ambient,
+    /// Parallel rays travelling in a direction, as from a very distant source like the sun
+    Directional { direction: Vec3f },
+    /// Light radiating in every direction from a position
+    Point {
+        position: Vec3f,
+        attenuation: Attenuation,
+    },
+    /// Light radiating from a position within a cone around a direction. Angles are in radians,
+    /// measured from the cone's axis. The light has full intensity within the inner angle, and
+    /// fades to nothing at the outer angle
+    Spot {
+        position: Vec3f,
+        direction: Vec3f,
+        inner_angle: f32,
+        outer_angle: f32,
+        attenuation: Attenuation,
+    },
+}
+
+/// A light used for per pixel lighting, by meshes using [LightingMode::Pixel](crate::LightingMode::Pixel)
+#[derive(Clone, Debug)]
+pub struct Light {
+    name: String,
+    pub kind: LightKind,
+    pub colour: RGB,
+    pub intensity: f32,
+    /// Whether the light is blocked by geometry. Only directional lights cast shadows, and only the
+    /// first such light is used
+    pub casts_shadows: bool,
+}
+
+impl Named for Light {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Light {
+    pub fn new(name: String, kind: LightKind, colour: RGB, intensity: f32) -> Self {
+        Self {
+            name,
+            kind,
+            colour,
+            intensity,
+            casts_shadows: false,
+        }
+    }
+
+    /// The direction of the light if it casts shadows
+    pub(crate) fn shadow_direction(&self) -> Option<Vec3f> {
+        match self.kind {
+            LightKind::Directional { direction } if self.casts_shadows => Some(direction),
+            _ => None,
+        }
+    }
+
+    /// Transforms the light into view space, ready to light pixels. A `shadowed` light is blocked
+    /// wherever the shadow map says so
+    pub(crate) fn to_view(&self, view_transform: &Mat4f, shadowed: bool) -> ViewLight {
+        let position = |position: Vec3f| transform_point(position, view_transform);
+        let direction = |direction: Vec3f| transform_normal(direction, view_transform);
+
+        let kind = match self.kind {
+            LightKind::Ambient => ViewLightKind::Ambient,
+
+            LightKind::Directional { direction: dir } => ViewLightKind::Directional {
+                to_light: direction(dir) * -1.0,
+                shadowed,
+            },
+
+            LightKind::Point {
+                position: pos,
+                attenuation,
+            } => ViewLightKind::Point {
+                position: position(pos),
+                attenuation,
+            },
+
+            LightKind::Spot {
+                position: pos,
+                direction: dir,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                let cos_inner = inner_angle.cos();
+                let cos_outer = outer_angle.cos();
+
+                ViewLightKind::Spot {
+                    position: position(pos),
+                    direction: direction(dir),
+                    cos_outer,
+                    cos_range_inv: 1.0 / (cos_inner - cos_outer).max(f32::EPSILON),
+                    attenuation,
+                }
+            }
+        };
+
+        ViewLight {
+            kind,
+            radiance: Vec3f::new(self.colour.r, self.colour.g, self.colour.b) * self.intensity,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ViewLightKind {
+    Ambient,
+    Directional {
+        /// Unit length direction from surfaces towards the light
+        to_light: Vec3f,
+        /// Whether the light is tested against the shadow map
+        shadowed: bool,
+    },
+    Point {
+        position: Vec3f,
+        attenuation: Attenuation,
+    },
+    Spot {
+        position: Vec3f,
+        direction: Vec3f,
+        cos_outer: f32,
+        cos_range_inv: f32,
+        attenuation: Attenuation,
+    },
+}
+
+/// A light in view space, with the cosines of its cone angles precomputed
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ViewLight {
+    kind: ViewLightKind,
+    /// Colour scaled by intensity
+    radiance: Vec3f,
+}
+
+impl ViewLight {
+    /// Distance from the camera used to choose which lights are kept when there are more than the
+    /// configured maximum. The light casting shadows comes first, so that the shadow map is only
+    /// drawn for a light that is used, followed by the other lights that reach everywhere
+    pub(crate) fn priority(&self) -> f32 {
+        match self.kind {
+            ViewLightKind::Directional { shadowed: true, .. } => -1.0,
+            ViewLightKind::Ambient | ViewLightKind::Directional { .. } => 0.0,
+            ViewLightKind::Point { position, .. } | ViewLightKind::Spot { position, .. } => {
+                position.magnitude()
+            }
+        }
+    }
+
+    /// Whether the light is tested against the shadow map
+    pub(crate) fn is_shadowed(&self) -> bool {
+        matches!(self.kind, ViewLightKind::Directional { shadowed: true, .. })
+    }
+}
+
+/// Specular reflectance of a surface lit per pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Specular {
+    pub colour: RGB,
+    /// Exponent of the specular term, where higher values give smaller, sharper highlights
+    pub shininess: f32,
+}
+
+impl Default for Specular {
+    fn default() -> Self {
+        Self {
+            colour: RGB::new(0.5, 0.5, 0.5),
+            shininess: 32.0,
+        }
+    }
+}
+
+/// A point on a surface to be lit per pixel
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Surface {
+    pub albedo: Vec3f,
+    /// Scales the ambient light reaching the surface
+    pub ambient: Vec3f,
+    pub specular: Specular,
+    /// Position in view space, where the camera is at the origin
+    pub position: Vec3f,
+    /// Unit length normal in view space
+    pub normal: Vec3f,
+}
+
+/// Schlick's approximation of the fraction of light reflected by a surface, given the reflectance
+/// when looking straight at it and the cosine of the angle between the view and the normal
+#[inline(always)]
+pub(crate) fn fresnel_schlick(reflectivity: f32, cos_theta: f32) -> f32 {
+    reflectivity + (1.0 - reflectivity) * (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// Lights a surface with the Blinn-Phong model
+pub(crate) fn blinn_phong(
+    lights: &[ViewLight],
+    shadow_map: Option<&ShadowMap>,
+    surface: &Surface,
+) -> Vec3f {
+    let Surface {
+        albedo,
+        ambient,
+        specular,
+        position,
+        normal,
+    } = *surface;
+
+    let to_camera = (position * -1.0).normalise();
+    let mut diffuse_sum = Vec3f::uniform(0.0);
+    let mut specular_sum = Vec3f::uniform(0.0);
+
+    for light in lights.iter() {
+        let (to_light, intensity) = match light.kind {
+            ViewLightKind::Ambient => {
+                diffuse_sum += Vec3f::new(
+                    light.radiance.x * ambient.x,
+                    light.radiance.y * ambient.y,
+                    light.radiance.z * ambient.z,
+                );
+                continue;
+            }
+
+            ViewLightKind::Directional { to_light, shadowed } => match shadow_map {
+                Some(shadow_map) if shadowed => (to_light, shadow_map.visibility(position)),
+                _ => (to_light, 1.0),
+            },
+
+            ViewLightKind::Point {
+                position: light_position,
+                attenuation,
+            } => {
+                let offset = light_position - position;
+                let distance = offset.magnitude();
+                // a surface at the light has no direction to it
+                if distance <= f32::EPSILON {
+                    continue;
+                }
+                (offset / distance, attenuation.factor(distance))
+            }
+
+            ViewLightKind::Spot {
+                position: light_position,
+                direction,
+                cos_outer,
+                cos_range_inv,
+                attenuation,
+            } => {
+                let offset = light_position - position;
+                let distance = offset.magnitude();
+                if distance <= f32::EPSILON {
+                    continue;
+                }
+                let to_light = offset / distance;
+
+                let cone = ((-to_light.dot(direction) - cos_outer) * cos_range_inv).clamp(0.0, 1.0);
+                (to_light, cone * attenuation.factor(distance))
+            }
+        };
+
+        let lambert = normal.dot(to_light);
+        if lambert <= 0.0 || intensity <= 0.0 {
+            continue;
+        }
+
+        let half = (to_light + to_camera).normalise();
+        let highlight = normal.dot(half).max(0.0).powf(specular.shininess);
+
+        diffuse_sum += light.radiance * (lambert * intensity);
+        specular_sum += light.radiance * (highlight * intensity);
+    }
+
+    Vec3f::new(
+        albedo.x * diffuse_sum.x + specular.colour.r * specular_sum.x,
+        albedo.y * diffuse_sum.y + specular.colour.g * specular_sum.y,
+        albedo.z * diffuse_sum.z + specular.colour.b * specular_sum.z,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::Vec3f;
+
+    use crate::{
+        colour::RGB,
+        config::RenderConfig,
+        material::Material,
+        model::LightingMode,
+        shapes::unit_cube_mesh,
+        testing::{assert_grey, centre, pixel, render, scene, test_config, white, HEIGHT, WIDTH},
+    };
+
+    use super::{
+        blinn_phong, fresnel_schlick, Attenuation, Light, LightKind, Lighting, Specular, Surface,
+        ViewLight, ViewLightKind,
+    };
+
+    /// Renders a single frame of a white cube in front of the camera, lit from the given direction
+    fn render_lit_cube(direction: Vec3f) -> Vec<RGB> {
+        let (mut renderer, _) = scene(
+            test_config(),
+            white(unit_cube_mesh()),
+            Vec3f::new(0.0, 0.0, 3.0),
+        );
+        renderer.set_lighting(Some(Lighting::new(
+            direction,
+            RGB::new(0.5, 0.5, 0.5),
+            RGB::new(0.25, 0.25, 0.25),
+        )));
+
+        render(&mut renderer)
+    }
+
+    /// Renders a single frame of a white cube in front of the camera, lit per pixel by the given
+    /// lights
+    fn render_pixel_lit_cube(
+        lights: &[(LightKind, f32)],
+        specular: Specular,
+        max_lights: usize,
+    ) -> Vec<RGB> {
+        let config = RenderConfig {
+            max_lights,
+            ..test_config()
+        };
+        let (mut renderer, cube) =
+            scene(config, white(unit_cube_mesh()), Vec3f::new(0.0, 0.0, 3.0));
+
+        for (i, &(kind, intensity)) in lights.iter().enumerate() {
+            let light = Light::new(format!("light {i}"), kind, RGB::WHITE, intensity);
+            renderer.assets_mut().insert_light(light);
+        }
+
+        let mut material = Material::new("material".into());
+        material.specular = specular;
+        let material = renderer.assets_mut().insert_material(material);
+        renderer
+            .assets_mut()
+            .set_mesh_material(cube, Some(material));
+        renderer
+            .assets_mut()
+            .set_mesh_lighting(cube, LightingMode::Pixel);
+
+        render(&mut renderer)
+    }
+
+    #[test]
+    fn vertex_lighting() {
+        // light travelling away from the camera hits the face nearest to it head on
+        let pixels = render_lit_cube(Vec3f::new(0.0, 0.0, 1.0));
+        assert_grey(centre(&pixels), 0.75);
+
+        // light travelling down only reaches the front face through ambient light
+        let pixels = render_lit_cube(Vec3f::new(0.0, -1.0, 0.0));
+        assert_grey(centre(&pixels), 0.25);
+    }
+
+    #[test]
+    fn pixel_lighting() {
+        let no_specular = Specular {
+            colour: RGB::BLACK,
+            shininess: 1.0,
+        };
+        let ambient = (LightKind::Ambient, 0.25);
+        let directional = |intensity| {
+            (
+                LightKind::Directional {
+                    direction: Vec3f::new(0.0, 0.0, 1.0),
+                },
+                intensity,
+            )
+        };
+        let edge = |pixels: &[RGB]| pixel(pixels, WIDTH / 2 + 5, HEIGHT / 2 + 5);
+
+        let pixels = render_pixel_lit_cube(&[ambient, directional(1.0)], no_specular, 8);
+        assert_grey(centre(&pixels), 1.25);
+        assert_grey(edge(&pixels), 1.25);
+
+        // only the first two lights are used
+        let lights = [ambient, directional(1.0), directional(1.0)];
+        let pixels = render_pixel_lit_cube(&lights, no_specular, 2);
+        assert_grey(centre(&pixels), 1.25);
+
+        // a point light at the camera lights the centre of the face head on, and adds a highlight
+        let point = LightKind::Point {
+            position: Vec3f::new(0.0, 0.0, 0.0),
+            attenuation: Attenuation::new(1.0, 0.0, 0.0),
+        };
+        let pixels = render_pixel_lit_cube(&[(point, 1.0)], Specular::default(), 8);
+        assert!(centre(&pixels).r > 1.4, "{:?}", centre(&pixels));
+        assert!(
+            edge(&pixels).r < centre(&pixels).r - 0.2,
+            "{:?}",
+            edge(&pixels)
+        );
+
+        // a narrow spot light only reaches the centre of the face
+        let spot = LightKind::Spot {
+            position: Vec3f::new(0.0, 0.0, 0.0),
+            direction: Vec3f::new(0.0, 0.0, 1.0),
+            inner_angle: 0.02,
+            outer_angle: 0.05,
+            attenuation: Attenuation::new(1.0, 0.0, 0.0),
+        };
+        let pixels = render_pixel_lit_cube(&[ambient, (spot, 1.0)], no_specular, 8);
+        assert!(centre(&pixels).r > 1.0, "{:?}", centre(&pixels));
+        assert_grey(edge(&pixels), 0.25);
+    }
+
+    #[test]
+    fn surface_at_light_is_skipped() {
+        let position = Vec3f::new(0.0, 0.0, 2.0);
+        let attenuation = Attenuation::new(1.0, 0.0, 0.0);
+        let lights = [
+            ViewLight {
+                kind: ViewLightKind::Ambient,
+                radiance: Vec3f::uniform(0.25),
+            },
+            ViewLight {
+                kind: ViewLightKind::Point {
+                    position,
+                    attenuation,
+                },
+                radiance: Vec3f::uniform(1.0),
+            },
+            ViewLight {
+                kind: ViewLightKind::Spot {
+                    position,
+                    direction: Vec3f::new(0.0, 0.0, 1.0),
+                    cos_outer: 0.0,
+                    cos_range_inv: 1.0,
+                    attenuation,
+                },
+                radiance: Vec3f::uniform(1.0),
+            },
+        ];
+        let surface = Surface {
+            albedo: Vec3f::uniform(1.0),
+            ambient: Vec3f::uniform(1.0),
+            specular: Specular::default(),
+            position,
+            normal: Vec3f::new(0.0, 0.0, -1.0),
+        };
+
+        // only the ambient light is left, rather than NaN from a zero length direction
+        let colour = blinn_phong(&lights, None, &surface);
+        assert_grey(RGB::new(colour.x, colour.y, colour.z), 0.25);
+    }
+
+    #[test]
+    fn fresnel_at_glancing_angles() {
+        // nearly everything is reflected at glancing angles
+        assert!(fresnel_schlick(0.5, 0.05) > 0.88);
+        assert!((fresnel_schlick(0.5, 0.0) - 1.0).abs() < 1e-5);
+    }
+}
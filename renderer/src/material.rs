@@ -0,0 +1,251 @@
+use crate::{
+    asset_manager::{AssetId, Named},
+    colour::RGB,
+    light::Specular,
+    texture::{Cubemap, Texture},
+};
+
+/// Surface properties shared by any number of meshes, such as those loaded from an MTL file
+/// alongside an OBJ
+#[derive(Clone, Debug)]
+pub struct Material {
+    name: String,
+    /// Diffuse colour, which tints the texture and vertex colours. Without a texture, this is the
+    /// colour of the surface
+    pub base_colour: RGB,
+    /// Sampled for the colour of the surface, depending on the mesh's
+    /// [ShadingMode](crate::ShadingMode)
+    pub diffuse_texture: Option<AssetId<Texture>>,
+    /// Scales the ambient light reaching the surface, such as a baked ambient occlusion map. Only
+    /// used with per pixel lighting
+    pub ambient_texture: Option<AssetId<Texture>>,
+    /// Scales the specular colour across the surface. Only used with per pixel lighting
+    pub specular_texture: Option<AssetId<Texture>>,
+    /// Tangent space normals, which add surface detail to per pixel lighting. Needs a mesh with
+    /// texture coordinates, from which tangents are generated
+    pub normal_texture: Option<AssetId<Texture>>,
+    /// Only used with per pixel lighting
+    pub specular: Specular,
+    /// Surroundings reflected by the surface, such as the skybox. With vertex lighting, the
+    /// reflection is blended with the lit vertex colours
+    pub environment_map: Option<AssetId<Cubemap>>,
+    /// Fraction of the environment reflected when looking straight at the surface. More is
+    /// reflected at glancing angles, following Schlick's approximation of the Fresnel effect
+    pub reflectivity: f32,
+    /// Alpha applied to the whole surface, from 0.0 for invisible to 1.0 for solid. This only has
+    /// an effect when the mesh's [AlphaMode](crate::AlphaMode) uses alpha
+    pub opacity: f32,
+    /// Draws back facing triangles, rather than culling them
+    pub double_sided: bool,
+}
+
+impl Named for Material {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Material {
+    /// Creates a white, opaque, single sided material without any textures
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            base_colour: RGB::WHITE,
+            diffuse_texture: None,
+            ambient_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            specular: Specular::default(),
+            environment_map: None,
+            reflectivity: 0.04,
+            opacity: 1.0,
+            double_sided: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::Vec3f;
+
+    use crate::{
+        colour::{RGB, RGBA},
+        light::{Light, LightKind},
+        model::{AlphaMode, LightingMode, ShadingMode},
+        shapes::{unit_cube_mesh, unit_quad_mesh},
+        testing::{assert_grey, assert_near, centre, render, scene, test_config, white},
+        texture::{Cubemap, Texture},
+    };
+
+    use super::Material;
+
+    /// Renders a single frame of a white quad facing the camera, lit per pixel by a directional
+    /// light, and returns the colour of its centre. The quad is given a normal map holding the
+    /// same tangent space normal at every texel
+    fn render_normal_mapped_quad(normal: Option<Vec3f>, direction: Vec3f) -> RGB {
+        let (mut renderer, quad) = scene(
+            test_config(),
+            white(unit_quad_mesh()),
+            Vec3f::new(0.0, 0.0, 3.0),
+        );
+
+        let sun = Light::new(
+            "sun".into(),
+            LightKind::Directional { direction },
+            RGB::WHITE,
+            1.0,
+        );
+        renderer.assets_mut().insert_light(sun);
+
+        let mut material = Material::new("no specular".into());
+        material.specular.colour = RGB::BLACK;
+        material.normal_texture = normal.map(|normal| {
+            let texel = RGBA::new(
+                normal.x * 0.5 + 0.5,
+                normal.y * 0.5 + 0.5,
+                normal.z * 0.5 + 0.5,
+                1.0,
+            );
+            let texture = Texture::from_pixels("normals".into(), 2, 2, vec![texel; 4]);
+            renderer.assets_mut().insert_texture(texture)
+        });
+        let material = renderer.assets_mut().insert_material(material);
+        renderer
+            .assets_mut()
+            .set_mesh_material(quad, Some(material));
+        renderer
+            .assets_mut()
+            .set_mesh_lighting(quad, LightingMode::Pixel);
+
+        centre(&render(&mut renderer))
+    }
+
+    #[test]
+    fn material_without_texture_uses_base_colour() {
+        let base_colour = RGB::new(0.25, 0.5, 0.75);
+        let render_with = |opacity: f32, alpha_mode: AlphaMode| {
+            let (mut renderer, cube) = scene(
+                test_config(),
+                white(unit_cube_mesh()),
+                Vec3f::new(0.0, 0.0, 3.0),
+            );
+
+            let mut material = Material::new("material".into());
+            material.base_colour = base_colour;
+            material.opacity = opacity;
+            let material = renderer.assets_mut().insert_material(material);
+            renderer
+                .assets_mut()
+                .set_mesh_material(cube, Some(material));
+            renderer.assets_mut().set_mesh_alpha_mode(cube, alpha_mode);
+
+            centre(&render(&mut renderer))
+        };
+
+        assert_eq!(render_with(1.0, AlphaMode::Opaque), base_colour);
+
+        // blended over the black background
+        let blended = render_with(0.5, AlphaMode::Blend);
+        assert!((blended.r - 0.125).abs() < 1e-4, "{blended:?}");
+        assert!((blended.b - 0.375).abs() < 1e-4, "{blended:?}");
+    }
+
+    #[test]
+    fn material_keeps_chosen_shading() {
+        let render_with = |shading: Option<ShadingMode>| {
+            let (mut renderer, quad) = scene(
+                test_config(),
+                white(unit_quad_mesh()),
+                Vec3f::new(0.0, 0.0, 3.0),
+            );
+            if let Some(shading) = shading {
+                renderer.assets_mut().set_mesh_shading(quad, shading);
+            }
+
+            let texture =
+                Texture::from_pixels("green".into(), 2, 2, vec![RGBA::from(RGB::GREEN); 4]);
+            let mut material = Material::new("textured".into());
+            material.diffuse_texture = Some(renderer.assets_mut().insert_texture(texture));
+            let material = renderer.assets_mut().insert_material(material);
+            renderer
+                .assets_mut()
+                .set_mesh_material(quad, Some(material));
+
+            centre(&render(&mut renderer))
+        };
+
+        // meshes on the default shading mode switch to the material's texture
+        assert_eq!(render_with(None), RGB::GREEN);
+        assert_eq!(render_with(Some(ShadingMode::Flat(RGB::RED))), RGB::RED);
+    }
+
+    #[test]
+    fn normal_mapping() {
+        // the light is tilted away from the quad's normal, but lines up with the mapped normals,
+        // which lean along the tangent and bitangent. The tangent points along +x, following u,
+        // while the bitangent points up the texture, towards decreasing v, which is -y
+        let towards_tangent = Vec3f::new(-0.6, 0.0, 0.8);
+        let towards_bitangent = Vec3f::new(0.0, 0.6, 0.8);
+
+        assert_grey(render_normal_mapped_quad(None, towards_tangent), 0.8);
+        assert_grey(
+            render_normal_mapped_quad(Some(Vec3f::new(0.6, 0.0, 0.8)), towards_tangent),
+            1.0,
+        );
+        assert_grey(
+            render_normal_mapped_quad(Some(Vec3f::new(0.0, 0.6, 0.8)), towards_bitangent),
+            1.0,
+        );
+        // a flat normal map leaves the lighting unchanged
+        assert_grey(
+            render_normal_mapped_quad(Some(Vec3f::new(0.0, 0.0, 1.0)), towards_tangent),
+            0.8,
+        );
+    }
+
+    #[test]
+    fn environment_reflections() {
+        // the quad faces back along -z, so reflects the -z face straight back at the camera
+        let face_colours = [
+            RGB::RED,
+            RGB::RED,
+            RGB::RED,
+            RGB::RED,
+            RGB::RED,
+            RGB::new(0.5, 1.0, 0.25),
+        ];
+        let render_with = |reflectivity: f32, lighting: LightingMode| {
+            // without any lights or vertex colours, the only colour is what's reflected
+            let mut mesh = unit_quad_mesh();
+            for vertex in mesh.vertices.iter_mut() {
+                vertex.colour = Vec3f::uniform(0.0);
+            }
+            let (mut renderer, quad) = scene(test_config(), mesh, Vec3f::new(0.0, 0.0, 3.0));
+
+            let faces = face_colours
+                .map(|colour| Texture::from_pixels("face".into(), 1, 1, vec![RGBA::from(colour)]));
+            let cubemap = renderer
+                .assets_mut()
+                .insert_cubemap(Cubemap::new("environment".into(), faces));
+
+            let mut material = Material::new("mirror".into());
+            material.environment_map = Some(cubemap);
+            material.reflectivity = reflectivity;
+            let material = renderer.assets_mut().insert_material(material);
+            renderer
+                .assets_mut()
+                .set_mesh_material(quad, Some(material));
+            renderer.assets_mut().set_mesh_lighting(quad, lighting);
+
+            centre(&render(&mut renderer))
+        };
+
+        for lighting in [LightingMode::Pixel, LightingMode::Vertex] {
+            assert_near(render_with(1.0, lighting), face_colours[5], 1e-4);
+
+            let half = render_with(0.5, lighting);
+            assert!((half.r - 0.25).abs() < 1e-4, "{lighting:?} {half:?}");
+            assert!((half.g - 0.5).abs() < 1e-4, "{lighting:?} {half:?}");
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, ops::Range};
 
 use collections::SparseMap;
 use maths::{
@@ -8,20 +8,85 @@ use maths::{
 
 use crate::{
     asset_manager::{AssetId, Named},
+    camera::View,
+    colour::RGB,
+    material::Material,
     renderer::RendererState,
+    shader::{Shader, VertexShader},
     texture::Texture,
 };
 
 use super::{
     triangle::{ProjectedTriangle, TriangleProjector},
-    vertex::{transform_point, Vertex},
+    vertex::{transform_direction, transform_normal, transform_point, Vertex},
 };
 
+/// Determines how the colour of each pixel is computed when rasterising a mesh, before it's tinted
+/// by the base colour of the mesh's material
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ShadingMode {
+    /// A single colour across the whole mesh
+    Flat(RGB),
+    /// Perspective correct interpolation of the vertex colours
+    #[default]
+    VertexColour,
+    /// Diffuse texture sampled at the interpolated texture coordinates. Falls back to
+    /// [ShadingMode::VertexColour] if the mesh's material has no diffuse texture
+    Texture,
+    /// Diffuse texture modulated by the interpolated vertex colour. Falls back to
+    /// [ShadingMode::VertexColour] if the mesh's material has no diffuse texture
+    TextureVertexColour,
+}
+
+impl ShadingMode {
+    /// The shading mode a mesh uses by default, depending on whether its material has a diffuse
+    /// texture
+    pub fn default_for(texture_id: Option<AssetId<Texture>>) -> Self {
+        if texture_id.is_some() {
+            Self::Texture
+        } else {
+            Self::VertexColour
+        }
+    }
+}
+
+/// Determines how the alpha channel of a pixel's colour is used
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    #[default]
+    Opaque,
+    /// Pixels with alpha below the threshold are discarded, and the rest are drawn as opaque
+    Cutout(f32),
+    /// Pixels are blended with the framebuffer, weighted by alpha. Blended meshes are drawn in a
+    /// separate pass after all opaque geometry, sorted back to front, and do not write depth. They
+    /// still cast solid shadows
+    Blend,
+}
+
+/// Determines how lights affect the colour of a mesh
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LightingMode {
+    /// Vertex colours are lit at each vertex by the renderer's [Lighting](crate::Lighting), if it
+    /// has one
+    #[default]
+    Vertex,
+    /// Pixels are lit by each [Light](crate::Light) with the Blinn-Phong model, using normals
+    /// interpolated across each triangle, and the specular properties of the mesh's material
+    Pixel,
+}
+
 pub struct Mesh {
     name: String,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<usize>,
-    pub texture_id: Option<AssetId<Texture>>,
+    /// Surface properties, or `None` to use those of [Material::new]
+    pub material_id: Option<AssetId<Material>>,
+    /// Custom shaders used in place of, or alongside, the built in shading
+    pub shader_id: Option<AssetId<Shader>>,
+    pub shading: ShadingMode,
+    pub alpha_mode: AlphaMode,
+    pub lighting: LightingMode,
     local_bounds: AABB<Vec3f>,
 
     pub instances: SparseMap<MeshInstance>,
@@ -35,12 +100,7 @@ impl Named for Mesh {
 }
 
 impl Mesh {
-    pub fn new(
-        name: String,
-        vertices: Vec<Vertex>,
-        indices: Vec<usize>,
-        texture_id: Option<AssetId<Texture>>,
-    ) -> Self {
+    pub fn new(name: String, vertices: Vec<Vertex>, indices: Vec<usize>) -> Self {
         println!("Mesh created with {} triangles", indices.len() / 3);
 
         let local_bounds = find_bounds(&vertices);
@@ -49,7 +109,11 @@ impl Mesh {
             name,
             vertices,
             indices,
-            texture_id,
+            material_id: None,
+            shader_id: None,
+            shading: ShadingMode::default(),
+            alpha_mode: AlphaMode::default(),
+            lighting: LightingMode::default(),
             local_bounds,
 
             instances: SparseMap::new(),
@@ -64,6 +128,16 @@ impl Mesh {
             .iter()
             .map(|vertex| transform_point(vertex.position, local_transform))
             .collect();
+        let world_normals = self
+            .vertices
+            .iter()
+            .map(|vertex| transform_normal(vertex.normal, local_transform))
+            .collect();
+        let world_tangents = self
+            .vertices
+            .iter()
+            .map(|vertex| transform_direction(Vec3f::from(vertex.tangent), local_transform))
+            .collect();
 
         let id = self
             .free_ids
@@ -74,6 +148,8 @@ impl Mesh {
             id,
             MeshInstance {
                 world_positions,
+                world_normals,
+                world_tangents,
                 world_bounds,
                 view_bounds: world_bounds,
             },
@@ -99,6 +175,15 @@ impl Mesh {
         {
             *world_position = transform_point(vertex.position, local_transform);
         }
+
+        for (world_normal, vertex) in instance.world_normals.iter_mut().zip(self.vertices.iter()) {
+            *world_normal = transform_normal(vertex.normal, local_transform);
+        }
+
+        for (world_tangent, vertex) in instance.world_tangents.iter_mut().zip(self.vertices.iter())
+        {
+            *world_tangent = transform_direction(Vec3f::from(vertex.tangent), local_transform);
+        }
     }
 
     pub fn update_all_view_bounds(&mut self, view_transform: &Mat4f) {
@@ -107,17 +192,39 @@ impl Mesh {
         }
     }
 
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Projects the triangles of an instance with indices in the given range onto the view, which
+    /// may produce extra triangles where they are clipped. With `depth_only` set, only the vertex
+    /// positions are projected, after running the vertex shader
     pub fn iter_instance_triangles<'a>(
         &'a self,
         state: &'a RendererState,
-        instance_index: usize,
+        view: &'a View,
+        instance: &'a MeshInstance,
+        vertex_shader: Option<&'a dyn VertexShader>,
+        depth_only: bool,
+        triangles: Range<usize>,
     ) -> impl Iterator<Item = ProjectedTriangle> + 'a {
-        TriangleProjector::new(state, self, self.instances.get(instance_index).unwrap())
+        let indices = &self.indices[triangles.start * 3..triangles.end * 3];
+        TriangleProjector::new(
+            state,
+            view,
+            self,
+            instance,
+            vertex_shader,
+            depth_only,
+            indices,
+        )
     }
 }
 
 pub struct MeshInstance {
     pub world_positions: Vec<Vec3f>,
+    pub world_normals: Vec<Vec3f>,
+    pub world_tangents: Vec<Vec3f>,
     pub world_bounds: AABB<Vec3f>,
     pub view_bounds: AABB<Vec3f>,
 }
@@ -3,7 +3,10 @@ mod model;
 mod triangle;
 mod vertex;
 
-pub use mesh::{Mesh, MeshInstance};
-pub use model::{Model, ModelInstance, load_obj};
+pub use mesh::{AlphaMode, LightingMode, Mesh, MeshInstance, ShadingMode};
+pub use model::{load_obj, Model, ModelInstance};
 pub use triangle::ProjectedTriangle;
 pub use vertex::Vertex;
+pub(crate) use vertex::{
+    generate_tangents, transform_direction, transform_normal, transform_point,
+};
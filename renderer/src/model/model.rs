@@ -5,15 +5,22 @@ use std::{
 };
 
 use collections::SparseMap;
-use maths::linear::{Vec2f, Vec3f};
+use maths::linear::{Vec2f, Vec3f, Vec4f};
 
 use crate::{
     asset_manager::{AssetId, Named},
+    colour::RGB,
+    light::Specular,
+    material::Material,
     texture::Texture,
     util::normalise_path,
 };
 
-use super::{mesh::Mesh, vertex::Vertex, MeshInstance};
+use super::{
+    mesh::Mesh,
+    vertex::{generate_normals, generate_tangents, Vertex},
+    MeshInstance,
+};
 
 pub struct Model {
     name: String,
@@ -45,10 +52,22 @@ pub struct ModelInstance {
 }
 
 pub struct Obj {
+    /// Meshes along with the index of their material, if they have one
     pub meshes: Vec<(Mesh, Option<usize>)>,
+    pub materials: Vec<ObjMaterial>,
+    /// Every texture used by the materials, each loaded once
     pub textures: Vec<Texture>,
 }
 
+/// A material from an MTL file, with its textures as indices into [Obj::textures]
+pub struct ObjMaterial {
+    pub material: Material,
+    pub diffuse_texture: Option<usize>,
+    pub ambient_texture: Option<usize>,
+    pub specular_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+}
+
 pub fn load_obj(
     path: impl AsRef<Path> + fmt::Debug,
     triangulate: bool,
@@ -66,28 +85,112 @@ pub fn load_obj(
     )?;
     let mtls = mtls?;
 
-    let textures = load_mtls(&mtls, path.as_ref().parent().unwrap());
+    let (materials, textures) = load_mtls(&mtls, path.as_ref().parent().unwrap())?;
     let meshes = load_meshes(&obj_models, reverse_winding, flip_uv_y);
 
-    Ok(Obj { meshes, textures })
+    Ok(Obj {
+        meshes,
+        materials,
+        textures,
+    })
 }
 
-fn load_mtls(mtls: &[tobj::Material], dir: impl AsRef<Path>) -> Vec<Texture> {
-    let mut textures = Vec::with_capacity(mtls.len());
+fn load_mtls(
+    mtls: &[tobj::Material],
+    dir: impl AsRef<Path>,
+) -> Result<(Vec<ObjMaterial>, Vec<Texture>), anyhow::Error> {
+    let mut materials = Vec::with_capacity(mtls.len());
+    let mut textures = Vec::new();
+    let mut texture_indices = HashMap::new();
+
+    // loads each texture the first time it's used, returning its index
+    let mut load_texture = |texture_name: &Option<String>| -> Result<_, anyhow::Error> {
+        let Some(texture_name) = texture_name else {
+            return Ok(None);
+        };
+
+        let texture_path = normalise_path(dir.as_ref().join(texture_file_name(texture_name)));
+        if let Some(&index) = texture_indices.get(&texture_path) {
+            return Ok(Some(index));
+        }
+
+        textures.push(Texture::from_path_png(&texture_path)?);
+        texture_indices.insert(texture_path, textures.len() - 1);
+        Ok(Some(textures.len() - 1))
+    };
+
+    for mtl in mtls.iter() {
+        let rgb = |values: Option<[f32; 3]>, default: RGB| {
+            values.map_or(default, |[r, g, b]| RGB::new(r, g, b))
+        };
 
-    for material in mtls.iter() {
-        let Some(texture_name) = &material.diffuse_texture else {
-            continue;
+        let default = Specular::default();
+        let specular = Specular {
+            // illumination models 0 and 1 have no specular highlights
+            colour: if mtl.illumination_model.is_some_and(|illum| illum < 2) {
+                RGB::BLACK
+            } else {
+                rgb(mtl.specular, default.colour)
+            },
+            shininess: mtl.shininess.unwrap_or(default.shininess),
         };
 
-        let texture_path = dir.as_ref().join(texture_name);
-        let texture_path = normalise_path(texture_path);
+        let mut material = Material::new(mtl.name.to_owned());
+        material.base_colour = rgb(mtl.diffuse, RGB::WHITE);
+        material.specular = specular;
+        material.opacity = mtl.dissolve.unwrap_or(1.0);
 
-        let texture = Texture::from_path_png(texture_path).unwrap();
-        textures.push(texture);
+        materials.push(ObjMaterial {
+            material,
+            diffuse_texture: load_texture(&mtl.diffuse_texture)?,
+            ambient_texture: load_texture(&mtl.ambient_texture)?,
+            specular_texture: load_texture(&mtl.specular_texture)?,
+            // tobj reads `bump` and `map_Bump`, but not `norm`
+            normal_texture: load_texture(
+                &mtl.normal_texture
+                    .clone()
+                    .or_else(|| mtl.unknown_param.get("norm").cloned()),
+            )?,
+        });
     }
 
-    textures
+    Ok((materials, textures))
+}
+
+/// Finds the file name in the arguments of an MTL texture map statement, skipping options such as
+/// `-bm 1.0` which come before it. The rest of the line is the file name, which may contain spaces
+fn texture_file_name(statement: &str) -> &str {
+    // splits off the first whitespace separated token
+    fn next_token(s: &str) -> (&str, &str) {
+        let s = s.trim_start();
+        let end = s.find(char::is_whitespace).unwrap_or(s.len());
+        (&s[..end], &s[end..])
+    }
+
+    let mut rest = statement;
+    loop {
+        let (option, after) = next_token(rest);
+        // the number of arguments each option takes, where only the first of `-o`, `-s` and `-t`
+        // is required
+        let (required, optional) = match option {
+            "-blendu" | "-blendv" | "-bm" | "-boost" | "-cc" | "-clamp" | "-imfchan"
+            | "-texres" | "-type" => (1, 0),
+            "-mm" => (2, 0),
+            "-o" | "-s" | "-t" => (1, 2),
+            _ => break,
+        };
+
+        rest = after;
+        for i in 0..required + optional {
+            let (argument, after) = next_token(rest);
+            if argument.is_empty() || (i >= required && argument.parse::<f32>().is_err()) {
+                break;
+            }
+            rest = after;
+        }
+    }
+
+    rest.trim()
 }
 
 fn load_meshes(
@@ -112,28 +215,63 @@ fn load_meshes(
             }
         }
 
-        let vertices = obj_model
-            .mesh
-            .positions
-            .chunks_exact(3)
-            .zip(obj_model.mesh.texcoords.chunks_exact(2))
-            .map(|(position, tex_coord)| {
-                let tex_coord = Vec2f::new(
-                    tex_coord[0],
-                    if flip_uv_y {
-                        1.0 - tex_coord[1]
-                    } else {
-                        tex_coord[1]
-                    },
-                );
+        let mesh = &obj_model.mesh;
+        let vertex_count = mesh.positions.len() / 3;
+        let has_normals = mesh.normals.len() == mesh.positions.len();
+        let has_colours = mesh.vertex_color.len() == mesh.positions.len();
+        let has_tex_coords = mesh.texcoords.len() / 2 == vertex_count;
+
+        let mut vertices = (0..vertex_count)
+            .map(|i| {
+                let vec3 = |values: &[f32]| {
+                    Vec3f::new(values[i * 3], values[i * 3 + 1], values[i * 3 + 2])
+                };
+
+                let tex_coord = if has_tex_coords {
+                    let v = mesh.texcoords[i * 2 + 1];
+                    Vec2f::new(mesh.texcoords[i * 2], if flip_uv_y { 1.0 - v } else { v })
+                } else {
+                    Vec2f::new(0.0, 0.0)
+                };
 
                 Vertex {
-                    position: Vec3f::new(position[0], position[1], position[2]),
-                    colour: Vec3f::new(position[0], position[1], position[2]),
+                    position: vec3(&mesh.positions),
+                    // zero length normals are left as zero, to be replaced below
+                    normal: if has_normals && vec3(&mesh.normals).magnitude() > 0.0 {
+                        vec3(&mesh.normals).normalise()
+                    } else {
+                        Vec3f::uniform(0.0)
+                    },
+                    colour: if has_colours {
+                        vec3(&mesh.vertex_color)
+                    } else {
+                        Vec3f::uniform(1.0)
+                    },
                     tex_coord,
+                    tangent: Vec4f::default(),
                 }
             })
-            .collect();
+            .collect::<Vec<Vertex>>();
+
+        let zero_normals = vertices
+            .iter()
+            .any(|vertex| vertex.normal.magnitude() == 0.0);
+        if !has_normals {
+            generate_normals(&mut vertices, &indices);
+        } else if zero_normals {
+            // vertices without a usable normal take the normal of the faces using them
+            let mut generated = vertices.clone();
+            generate_normals(&mut generated, &indices);
+            for (vertex, generated) in vertices.iter_mut().zip(generated) {
+                if vertex.normal.magnitude() == 0.0 {
+                    vertex.normal = generated.normal;
+                }
+            }
+        }
+
+        if has_tex_coords {
+            generate_tangents(&mut vertices, &indices);
+        }
 
         let duplicates = names.entry(&obj_model.name).or_insert(0);
         let name = if *duplicates == 0 {
@@ -144,10 +282,46 @@ fn load_meshes(
         *duplicates += 1;
 
         meshes.push((
-            Mesh::new(name, vertices, indices, None),
+            Mesh::new(name, vertices, indices),
             obj_model.mesh.material_id,
         ));
     }
 
     meshes
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{load_meshes, texture_file_name};
+
+    #[test]
+    fn texture_options_are_skipped() {
+        assert_eq!(texture_file_name("diffuse.png"), "diffuse.png");
+        assert_eq!(texture_file_name("-bm 0.5 normal.png"), "normal.png");
+        assert_eq!(texture_file_name("-s 2 2 -o 0.5 brick.png"), "brick.png");
+        assert_eq!(texture_file_name("-o 0.5 -mm 0 1 brick.png"), "brick.png");
+        assert_eq!(
+            texture_file_name("-clamp on -blendu off textures/old brick.png"),
+            "textures/old brick.png"
+        );
+    }
+
+    #[test]
+    fn zero_length_normals_use_face_normal() {
+        let mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            normals: vec![0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        let meshes = load_meshes(&[tobj::Model::new(mesh, "triangle".into())], false, false);
+
+        for vertex in meshes[0].0.vertices.iter() {
+            let normal = vertex.normal;
+            assert!(
+                normal.x == 0.0 && normal.y == 0.0 && normal.z == 1.0,
+                "expected a normal along +z, got {normal:?}"
+            );
+        }
+    }
+}
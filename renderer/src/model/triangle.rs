@@ -5,11 +5,20 @@ use maths::{
     linear::{Vec2f, Vec3f},
 };
 
-use crate::{asset_manager::AssetId, renderer::RendererState, texture::Texture, NEAR};
+use crate::{
+    asset_manager::AssetId,
+    camera::{Projection, View},
+    material::Material,
+    raster::EdgeFunctions,
+    renderer::RendererState,
+    shader::{Shader, Varyings, VertexInput, VertexShader},
+};
 
 use super::{
-    mesh::{Mesh, MeshInstance},
-    vertex::{clip_edge, transform_point, Vertex},
+    mesh::{AlphaMode, LightingMode, Mesh, MeshInstance, ShadingMode},
+    vertex::{
+        clip_edge, transform_direction, transform_normal, transform_point, ClipPlane, ViewVertex,
+    },
 };
 
 #[derive(Default)]
@@ -18,10 +27,27 @@ pub struct ProjectedTriangle {
     pub depth_inv: Vec3f,
     pub col_depth: [Vec3f; 3],
     pub tex_coords_depth: [Vec2f; 3],
+    /// Screen space derivatives of `tex_coords_depth` (x, y) and `depth_inv` (z), along the
+    /// horizontal and vertical axes respectively. Used to select a mip level per pixel
+    pub tex_coords_depth_dx: Vec3f,
+    pub tex_coords_depth_dy: Vec3f,
+    /// Varyings from the vertex shader, divided by depth like the other attributes
+    pub varyings_depth: [Varyings; 3],
+    /// View space positions, normals and tangents divided by depth, for per pixel lighting
+    pub positions_depth: [Vec3f; 3],
+    pub normals_depth: [Vec3f; 3],
+    pub tangents_depth: [Vec3f; 3],
+    /// Sign of the bitangent, from the first vertex
+    pub bitangent_sign: f32,
 
     pub two_area_inv: f32,
+    pub edges: EdgeFunctions,
     pub sat_edges: [Vec2f; 3],
-    pub texture_id: Option<AssetId<Texture>>
+    pub material_id: Option<AssetId<Material>>,
+    pub shader_id: Option<AssetId<Shader>>,
+    pub shading: ShadingMode,
+    pub alpha_mode: AlphaMode,
+    pub lighting: LightingMode,
 }
 
 impl ProjectedTriangle {
@@ -33,136 +59,307 @@ impl ProjectedTriangle {
         self.two_area_inv.is_sign_negative()
     }
 
-    pub fn is_textured(&self) -> bool {
-        self.texture_id.is_some()
+    pub fn is_degenerate(&self) -> bool {
+        self.edges.is_degenerate()
     }
-}
 
-pub struct TriangleProjector<'a> {
-    state: &'a RendererState,
-    mesh: &'a Mesh,
-    instance: &'a MeshInstance,
+    pub fn is_blended(&self) -> bool {
+        self.alpha_mode == AlphaMode::Blend
+    }
 
-    indices_iter: ChunksExact<'a, usize>,
-    split_triangle: Option<ProjectedTriangle>,
-}
+    /// View space depth of the nearest vertex
+    pub fn min_depth(&self) -> f32 {
+        1.0 / self.depth_inv.x.max(self.depth_inv.y).max(self.depth_inv.z)
+    }
 
-impl<'a> TriangleProjector<'a> {
-    pub fn new(state: &'a RendererState, mesh: &'a Mesh, instance: &'a MeshInstance) -> Self {
-        Self {
-            state,
-            mesh,
-            instance,
+    /// View space depth of the furthest vertex
+    pub fn max_depth(&self) -> f32 {
+        1.0 / self.depth_inv.x.min(self.depth_inv.y).min(self.depth_inv.z)
+    }
 
-            indices_iter: mesh.indices.chunks_exact(3),
-            split_triangle: None,
-        }
+    /// Average view space depth of the vertices, used to sort blended triangles
+    pub fn mean_depth(&self) -> f32 {
+        (1.0 / self.depth_inv.x + 1.0 / self.depth_inv.y + 1.0 / self.depth_inv.z) / 3.0
     }
 }
 
-impl<'a> Iterator for TriangleProjector<'a> {
-    type Item = ProjectedTriangle;
+/// Distance in pixels that triangles may extend beyond each edge of the screen before they are
+/// clipped. Triangles crossing the edge of the screen are mostly left whole, as the rasteriser only
+/// visits pixels on screen, and clipping just keeps vertices in a range it handles precisely
+const GUARD_BAND: f32 = 4096.0;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(triangle) = self.split_triangle.take() {
-            return Some(triangle);
-        }
+/// The near and far planes, and the four sides of the guard band
+const CLIP_PLANES: usize = 6;
+
+/// Each clip plane can add at most one vertex to a convex polygon
+const MAX_CLIPPED_VERTICES: usize = 3 + CLIP_PLANES;
+
+/// Planes bounding the visible region of view space
+struct Frustum {
+    /// Planes which triangles are clipped against
+    clip_planes: [ClipPlane; CLIP_PLANES],
+    /// The sides of the view frustum, used to reject triangles that are entirely off screen
+    side_planes: [ClipPlane; 4],
+}
+
+impl Frustum {
+    fn new(view: &View) -> Self {
+        // planes for the edges of the screen extended by a number of pixels, which pass through the
+        // camera for a perspective projection, or are parallel to the view direction otherwise
+        let sides = |extend: f32| {
+            let half_width = view.half_width + extend;
+            let half_height = view.half_height + extend;
+
+            match view.projection {
+                Projection::Perspective {
+                    focal_width,
+                    focal_height,
+                } => [
+                    ClipPlane::new(Vec3f::new(focal_width, 0.0, half_width), 0.0),
+                    ClipPlane::new(Vec3f::new(-focal_width, 0.0, half_width), 0.0),
+                    ClipPlane::new(Vec3f::new(0.0, focal_height, half_height), 0.0),
+                    ClipPlane::new(Vec3f::new(0.0, -focal_height, half_height), 0.0),
+                ],
+                Projection::Orthographic { scale } => [
+                    ClipPlane::new(Vec3f::new(scale, 0.0, 0.0), half_width),
+                    ClipPlane::new(Vec3f::new(-scale, 0.0, 0.0), half_width),
+                    ClipPlane::new(Vec3f::new(0.0, scale, 0.0), half_height),
+                    ClipPlane::new(Vec3f::new(0.0, -scale, 0.0), half_height),
+                ],
+            }
+        };
+        let [left, right, bottom, top] = sides(GUARD_BAND);
 
-        let indices = self.indices_iter.next()?;
-        let vertices = array::from_fn(|i| Vertex {
-            position: transform_point(
-                self.instance.world_positions[indices[i]],
-                self.state.camera.view_transform(),
-            ),
-            colour: self.mesh.vertices[indices[i]].colour,
-            tex_coord: self.mesh.vertices[indices[i]].tex_coord,
-        });
+        Self {
+            clip_planes: [
+                ClipPlane::new(Vec3f::new(0.0, 0.0, 1.0), -view.near),
+                ClipPlane::new(Vec3f::new(0.0, 0.0, -1.0), view.far),
+                left,
+                right,
+                bottom,
+                top,
+            ],
+            side_planes: sides(0.0),
+        }
+    }
 
-        match clip_triangle(vertices) {
-            ClipResult::None => self.next(),
+    /// Clips a triangle to the frustum, writing the vertices of the resulting convex polygon to
+    /// `polygon` and returning how many there are. Fewer than three means nothing is visible
+    fn clip_triangle(
+        &self,
+        vertices: [ViewVertex; 3],
+        polygon: &mut [ViewVertex; MAX_CLIPPED_VERTICES],
+    ) -> usize {
+        // reject triangle if it's entirely outside one side of the frustum
+        if self.side_planes.iter().any(|plane| {
+            vertices
+                .iter()
+                .all(|vertex| plane.distance(vertex.position) < 0.0)
+        }) {
+            return 0;
+        }
 
-            ClipResult::One(vertices) => Some(project_triangle(self.state, vertices, self.mesh.texture_id)),
+        polygon[..3].copy_from_slice(&vertices);
+        let mut len = 3;
+        let mut clipped = [ViewVertex::default(); MAX_CLIPPED_VERTICES];
+
+        for plane in self.clip_planes.iter() {
+            // skip plane if the polygon is entirely inside it, which is true for most triangles
+            if polygon[..len]
+                .iter()
+                .all(|vertex| plane.distance(vertex.position) >= 0.0)
+            {
+                continue;
+            }
 
-            ClipResult::Two(triangle1, triangle2) => {
-                self.split_triangle = Some(project_triangle(self.state, triangle2, self.mesh.texture_id));
-                Some(project_triangle(self.state, triangle1, self.mesh.texture_id))
+            len = clip_polygon(&polygon[..len], plane, &mut clipped);
+            if len < 3 {
+                return 0;
             }
+            polygon[..len].copy_from_slice(&clipped[..len]);
         }
+
+        len
     }
 }
 
-enum ClipResult {
-    None,
-    One([Vertex; 3]),
-    Two([Vertex; 3], [Vertex; 3]),
+/// Clips a convex polygon against a plane, using the Sutherland-Hodgman algorithm. Vertices stay in
+/// the same winding order
+fn clip_polygon(
+    polygon: &[ViewVertex],
+    plane: &ClipPlane,
+    clipped: &mut [ViewVertex; MAX_CLIPPED_VERTICES],
+) -> usize {
+    let mut len = 0;
+
+    for (i, &current) in polygon.iter().enumerate() {
+        let next = polygon[(i + 1) % polygon.len()];
+        let current_inside = plane.distance(current.position) >= 0.0;
+        let next_inside = plane.distance(next.position) >= 0.0;
+
+        if current_inside {
+            clipped[len] = current;
+            len += 1;
+        }
+
+        if current_inside && !next_inside {
+            clipped[len] = clip_edge(current, next, plane);
+            len += 1;
+        } else if !current_inside && next_inside {
+            clipped[len] = clip_edge(next, current, plane);
+            len += 1;
+        }
+    }
+
+    len
 }
 
-fn clip_triangle(vertices: [Vertex; 3]) -> ClipResult {
-    let v0_out_bounds = ((vertices[0].position.z < NEAR) as usize) << 2;
-    let v1_out_bounds = ((vertices[1].position.z < NEAR) as usize) << 1;
-    let v2_out_bounds = (vertices[2].position.z < NEAR) as usize;
-    let out_bounds = v0_out_bounds | v1_out_bounds | v2_out_bounds;
+pub struct TriangleProjector<'a> {
+    state: &'a RendererState,
+    view: &'a View,
+    mesh: &'a Mesh,
+    instance: &'a MeshInstance,
+    vertex_shader: Option<&'a dyn VertexShader>,
+    /// Only positions are needed, such as for the shadow map, so the vertex shader still moves
+    /// vertices but normals, tangents, lighting and varyings are skipped
+    depth_only: bool,
+    frustum: Frustum,
 
-    match out_bounds {
-        0b000 => ClipResult::One(vertices),
-        0b111 => ClipResult::None,
+    indices_iter: ChunksExact<'a, usize>,
+    /// The current triangle after clipping, which is drawn as a fan of triangles around the first
+    /// vertex
+    polygon: [ViewVertex; MAX_CLIPPED_VERTICES],
+    polygon_len: usize,
+    /// Index of the second vertex of the next triangle in the fan
+    fan_index: usize,
+}
 
-        0b001 => {
-            let v2 = clip_edge(vertices[1], vertices[2]);
-            let v3 = clip_edge(vertices[0], vertices[2]);
-            ClipResult::Two([vertices[0], vertices[1], v2], [v2, v3, vertices[0]])
+impl<'a> TriangleProjector<'a> {
+    pub fn new(
+        state: &'a RendererState,
+        view: &'a View,
+        mesh: &'a Mesh,
+        instance: &'a MeshInstance,
+        vertex_shader: Option<&'a dyn VertexShader>,
+        depth_only: bool,
+        indices: &'a [usize],
+    ) -> Self {
+        Self {
+            state,
+            view,
+            mesh,
+            instance,
+            vertex_shader,
+            depth_only,
+            frustum: Frustum::new(view),
+
+            indices_iter: indices.chunks_exact(3),
+            polygon: [ViewVertex::default(); MAX_CLIPPED_VERTICES],
+            polygon_len: 0,
+            fan_index: 0,
         }
+    }
+}
 
-        0b010 => {
-            let v1 = clip_edge(vertices[0], vertices[1]);
-            let v3 = clip_edge(vertices[2], vertices[1]);
-            ClipResult::Two([vertices[2], vertices[0], v1], [v1, v3, vertices[2]])
+impl<'a> TriangleProjector<'a> {
+    /// Transforms a vertex of the instance into view space, running the vertex shader if there is
+    /// one, and lights its colour. Only the position is kept for depth only projection
+    fn view_vertex(&self, index: usize) -> ViewVertex {
+        let vertex = &self.mesh.vertices[index];
+        let mut position = self.instance.world_positions[index];
+        let mut normal = self.instance.world_normals[index];
+        let mut varyings = Varyings::default();
+
+        if let Some(shader) = self.vertex_shader {
+            let input = VertexInput {
+                position,
+                normal,
+                colour: vertex.colour,
+                tex_coord: vertex.tex_coord,
+            };
+            let output = shader.shade(&input, &self.state.uniforms);
+            position = output.position;
+            normal = output.normal;
+            varyings = output.varyings;
         }
 
-        0b100 => {
-            let v0 = clip_edge(vertices[2], vertices[0]);
-            let v3 = clip_edge(vertices[1], vertices[0]);
-            ClipResult::Two([vertices[1], vertices[2], v0], [v0, v3, vertices[1]])
+        if self.depth_only {
+            return ViewVertex {
+                position: transform_point(position, &self.view.transform),
+                ..Default::default()
+            };
         }
 
-        0b011 => {
-            let v1 = clip_edge(vertices[0], vertices[1]);
-            let v2 = clip_edge(vertices[0], vertices[2]);
-            ClipResult::One([vertices[0], v1, v2])
+        // the vertex shader may have changed the normal, so the tangent is made perpendicular to it
+        let tangent = self.instance.world_tangents[index];
+        let normal_len_sq = normal.dot(normal);
+        let tangent = if self.vertex_shader.is_some() && normal_len_sq > 0.0 {
+            tangent - normal * (normal.dot(tangent) / normal_len_sq)
+        } else {
+            tangent
+        };
+
+        // Gouraud shading, where lighting is found per vertex and interpolated like the colour
+        let colour = match (self.mesh.lighting, &self.state.lighting) {
+            (LightingMode::Vertex, Some(lighting)) => lighting.light_vertex(vertex.colour, normal),
+            _ => vertex.colour,
+        };
+
+        ViewVertex {
+            position: transform_point(position, &self.view.transform),
+            normal: transform_normal(normal, &self.view.transform),
+            tangent: transform_direction(tangent, &self.view.transform),
+            bitangent_sign: vertex.tangent.w,
+            colour,
+            tex_coord: vertex.tex_coord,
+            varyings,
         }
+    }
+}
 
-        0b101 => {
-            let v0 = clip_edge(vertices[1], vertices[0]);
-            let v2 = clip_edge(vertices[1], vertices[2]);
-            ClipResult::One([v0, vertices[1], v2])
-        }
+impl<'a> Iterator for TriangleProjector<'a> {
+    type Item = ProjectedTriangle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.fan_index + 2 > self.polygon_len {
+            let indices = self.indices_iter.next()?;
+            let vertices = array::from_fn(|i| self.view_vertex(indices[i]));
 
-        0b110 => {
-            let v0 = clip_edge(vertices[2], vertices[0]);
-            let v1 = clip_edge(vertices[2], vertices[1]);
-            ClipResult::One([v0, v1, vertices[2]])
+            self.polygon_len = self.frustum.clip_triangle(vertices, &mut self.polygon);
+            self.fan_index = 1;
         }
 
-        _ => unreachable!(),
+        let vertices = [
+            self.polygon[0],
+            self.polygon[self.fan_index],
+            self.polygon[self.fan_index + 1],
+        ];
+        self.fan_index += 1;
+
+        Some(project_triangle(self.view, vertices, self.mesh))
     }
 }
 
-fn project_triangle(state: &RendererState, vertices: [Vertex; 3], texture_id: Option<AssetId<Texture>>) -> ProjectedTriangle {
-    let depth_inv = Vec3f::from(array::from_fn(|i| 1.0 / vertices[i].position.z));
+fn project_triangle(view: &View, vertices: [ViewVertex; 3], mesh: &Mesh) -> ProjectedTriangle {
+    let depth_inv = Vec3f::from(array::from_fn(|i| view.depth_inv(vertices[i].position.z)));
     let col_depth = array::from_fn(|i| vertices[i].colour * depth_inv[i]);
     let tex_coords_depth = array::from_fn(|i| vertices[i].tex_coord * depth_inv[i]);
+    let varyings_depth = array::from_fn(|i| vertices[i].varyings.scale(depth_inv[i]));
+    let positions_depth = array::from_fn(|i| vertices[i].position * depth_inv[i]);
+    let normals_depth = array::from_fn(|i| vertices[i].normal * depth_inv[i]);
+    let tangents_depth = array::from_fn(|i| vertices[i].tangent * depth_inv[i]);
 
     let triangle = Triangle::from(array::from_fn(|i| {
-        Vec2f::new(
-            (state.focal_width() * vertices[i].position.x) * depth_inv[i]
-                + (state.framebuffer.half_width()),
-            (-state.focal_height() * vertices[i].position.y) * depth_inv[i]
-                + (state.framebuffer.half_height()),
-        )
+        view.project(vertices[i].position, depth_inv[i])
     }));
 
     let two_area_inv = 1.0 / Segment::new(triangle.b, triangle.a).edge_side(triangle.c);
 
+    let (tex_coords_depth_dx, tex_coords_depth_dy) = screen_gradients(
+        &triangle,
+        array::from_fn(|i| Vec3f::new(tex_coords_depth[i].x, tex_coords_depth[i].y, depth_inv[i])),
+    );
+
     let sat_edges = [
         (triangle.b - triangle.a).perpendicular(),
         (triangle.c - triangle.b).perpendicular(),
@@ -174,9 +371,38 @@ fn project_triangle(state: &RendererState, vertices: [Vertex; 3], texture_id: Op
         depth_inv,
         col_depth,
         tex_coords_depth,
+        tex_coords_depth_dx,
+        tex_coords_depth_dy,
+        varyings_depth,
+        positions_depth,
+        normals_depth,
+        tangents_depth,
+        bitangent_sign: vertices[0].bitangent_sign,
 
         two_area_inv,
+        edges: EdgeFunctions::new(&triangle),
         sat_edges,
-        texture_id
+        material_id: mesh.material_id,
+        shader_id: mesh.shader_id,
+        shading: mesh.shading,
+        alpha_mode: mesh.alpha_mode,
+        lighting: mesh.lighting,
     }
 }
+
+/// Calculates the rate of change of a set of attributes across the screen, given their values at
+/// each vertex. As the attributes are linear in screen space, the gradients are constant across the
+/// whole triangle
+fn screen_gradients(triangle: &Triangle<Vec2f>, values: [Vec3f; 3]) -> (Vec3f, Vec3f) {
+    let ab = triangle.b - triangle.a;
+    let ac = triangle.c - triangle.a;
+    let denom_inv = 1.0 / (ab.x * ac.y - ac.x * ab.y);
+
+    let delta_ab = values[1] - values[0];
+    let delta_ac = values[2] - values[0];
+
+    let dx = (delta_ab * ac.y - delta_ac * ab.y) * denom_inv;
+    let dy = (delta_ac * ab.x - delta_ab * ac.x) * denom_inv;
+
+    (dx, dy)
+}
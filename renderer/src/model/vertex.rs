@@ -1,24 +1,218 @@
 use maths::linear::{Mat4f, Vec2f, Vec3f, Vec4f};
 
-use crate::NEAR;
+use crate::shader::Varyings;
 
 #[derive(Clone, Copy, Default, Debug)]
 pub struct Vertex {
     pub position: Vec3f,
+    /// Unit length normal, facing out of the front of the surface
+    pub normal: Vec3f,
     pub colour: Vec3f,
     pub tex_coord: Vec2f,
+    /// Unit length direction of increasing u across the texture, perpendicular to the normal. The
+    /// w component is the sign of the bitangent, `normal.cross(tangent) * w`, which points up the
+    /// texture, towards decreasing v. This is zero for meshes without texture coordinates
+    pub tangent: Vec4f,
+}
+
+/// A vertex after it's been transformed into view space, with the attributes that are interpolated
+/// across its triangles
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ViewVertex {
+    pub position: Vec3f,
+    pub normal: Vec3f,
+    pub tangent: Vec3f,
+    pub bitangent_sign: f32,
+    pub colour: Vec3f,
+    pub tex_coord: Vec2f,
+    pub varyings: Varyings,
 }
 
 pub fn transform_point(point: Vec3f, transform: &Mat4f) -> Vec3f {
     Vec3f::from(*transform * Vec4f::from(point))
 }
 
-pub fn clip_edge(in_bounds: Vertex, out_bounds: Vertex) -> Vertex {
-    let t = (NEAR - in_bounds.position.z) / (out_bounds.position.z - in_bounds.position.z);
+/// Transforms a normal by the inverse transpose of the transform's upper 3x3, so that it stays
+/// perpendicular to the surface under non-uniform scaling. The cofactor matrix is used in place of
+/// the inverse transpose, as they differ only by the determinant, which is removed by normalising
+pub fn transform_normal(normal: Vec3f, transform: &Mat4f) -> Vec3f {
+    let column = |i: usize| Vec3f::new(transform[i][0], transform[i][1], transform[i][2]);
+    let (x, y, z) = (column(0), column(1), column(2));
+
+    (y.cross(z) * normal.x + z.cross(x) * normal.y + x.cross(y) * normal.z).normalise()
+}
+
+/// Transforms a direction, such as a tangent, which is unaffected by translation. The result is
+/// scaled along with the transform, and zero directions stay zero
+pub fn transform_direction(direction: Vec3f, transform: &Mat4f) -> Vec3f {
+    let column = |i: usize| Vec3f::new(transform[i][0], transform[i][1], transform[i][2]);
+
+    column(0) * direction.x + column(1) * direction.y + column(2) * direction.z
+}
+
+/// Sets the normal of each vertex to the average of the normals of the triangles using it,
+/// weighted by their area
+pub fn generate_normals(vertices: &mut [Vertex], indices: &[usize]) {
+    for vertex in vertices.iter_mut() {
+        vertex.normal = Vec3f::uniform(0.0);
+    }
+
+    for triangle in indices.chunks_exact(3) {
+        let a = vertices[triangle[0]].position;
+        let b = vertices[triangle[1]].position;
+        let c = vertices[triangle[2]].position;
+
+        // the magnitude of the cross product is twice the area of the triangle
+        let normal = (b - a).cross(c - a);
+
+        for &index in triangle {
+            vertices[index].normal += normal;
+        }
+    }
+
+    for vertex in vertices.iter_mut() {
+        // vertices not used by any triangle are left with a zero normal
+        if vertex.normal.magnitude() > 0.0 {
+            vertex.normal = vertex.normal.normalise();
+        }
+    }
+}
+
+/// Sets the tangent of each vertex from the texture coordinates of the triangles using it, which
+/// must have normals already. Vertices where the texture coordinates don't vary are left with a zero
+/// tangent
+pub fn generate_tangents(vertices: &mut [Vertex], indices: &[usize]) {
+    // the directions of increasing u and v across the surface
+    let mut tangents = vec![Vec3f::uniform(0.0); vertices.len()];
+    let mut bitangents = vec![Vec3f::uniform(0.0); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i]]);
+        let edge_ab = b.position - a.position;
+        let edge_ac = c.position - a.position;
+        let uv_ab = b.tex_coord - a.tex_coord;
+        let uv_ac = c.tex_coord - a.tex_coord;
+
+        // skip triangles whose texture coordinates are degenerate, relative to the lengths of the
+        // edges in texture space, so that finely tessellated meshes still get tangents
+        let det = uv_ab.x * uv_ac.y - uv_ac.x * uv_ab.y;
+        let uv_len = |uv: Vec2f| (uv.x * uv.x + uv.y * uv.y).sqrt();
+        if det.abs() <= f32::EPSILON * uv_len(uv_ab) * uv_len(uv_ac) {
+            continue;
+        }
+
+        let det_inv = 1.0 / det;
+        let tangent = (edge_ab * uv_ac.y - edge_ac * uv_ab.y) * det_inv;
+        let bitangent = (edge_ac * uv_ab.x - edge_ab * uv_ac.x) * det_inv;
+
+        for &index in triangle {
+            tangents[index] += tangent;
+            bitangents[index] += bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents.iter().zip(bitangents)) {
+        // make the tangent perpendicular to the normal
+        let normal = vertex.normal;
+        let perpendicular = *tangent - normal * normal.dot(*tangent);
+
+        if perpendicular.magnitude() <= f32::EPSILON * tangent.magnitude() {
+            vertex.tangent = Vec4f::new(0.0, 0.0, 0.0, 1.0);
+            continue;
+        }
+
+        let tangent = perpendicular.normalise();
+        // the bitangent points towards decreasing v
+        let sign = if normal.cross(tangent).dot(bitangent) > 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = Vec4f::new(tangent.x, tangent.y, tangent.z, sign);
+    }
+}
+
+/// A plane in view space, with the normal facing the side that is kept when clipping
+#[derive(Clone, Copy, Debug)]
+pub struct ClipPlane {
+    pub normal: Vec3f,
+    pub offset: f32,
+}
+
+impl ClipPlane {
+    pub fn new(normal: Vec3f, offset: f32) -> Self {
+        Self { normal, offset }
+    }
+
+    /// Signed distance of a point from the plane, scaled by the length of the normal. This is
+    /// negative for points which should be clipped
+    pub fn distance(&self, point: Vec3f) -> f32 {
+        self.normal.dot(point) + self.offset
+    }
+}
+
+/// Finds the vertex where an edge crosses the plane, interpolating each attribute. The edge is
+/// always clipped from the inside, so edges shared between triangles get the same new vertex
+pub fn clip_edge(in_bounds: ViewVertex, out_bounds: ViewVertex, plane: &ClipPlane) -> ViewVertex {
+    let in_distance = plane.distance(in_bounds.position);
+    let out_distance = plane.distance(out_bounds.position);
+    let t = in_distance / (in_distance - out_distance);
 
-    Vertex {
+    ViewVertex {
         position: in_bounds.position.lerp(out_bounds.position, t),
+        normal: in_bounds.normal.lerp(out_bounds.normal, t),
+        tangent: in_bounds.tangent.lerp(out_bounds.tangent, t),
+        // the sign is the same across a triangle, except where texture coordinates are mirrored
+        bitangent_sign: in_bounds.bitangent_sign,
         colour: in_bounds.colour.lerp(out_bounds.colour, t),
         tex_coord: in_bounds.tex_coord.lerp(out_bounds.tex_coord, t),
+        varyings: in_bounds.varyings.lerp(&out_bounds.varyings, t),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::{Vec2f, Vec3f};
+
+    use super::{generate_tangents, Vertex};
+
+    #[test]
+    fn tangents_for_finely_tessellated_texture() {
+        // a quad split into many cells, which together span a small part of the texture, so the
+        // texture coordinates change very little across each triangle
+        let cells = 256;
+        let uv_scale = 0.001;
+
+        let mut vertices = Vec::new();
+        for y in 0..=cells {
+            for x in 0..=cells {
+                let (s, t) = (x as f32 / cells as f32, y as f32 / cells as f32);
+                vertices.push(Vertex {
+                    position: Vec3f::new(s, t, 0.0),
+                    normal: Vec3f::new(0.0, 0.0, -1.0),
+                    tex_coord: Vec2f::new(s * uv_scale, (1.0 - t) * uv_scale),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let mut indices = Vec::new();
+        for y in 0..cells {
+            for x in 0..cells {
+                let i = y * (cells + 1) + x;
+                let above = i + cells + 1;
+                indices.extend([i, above, i + 1, i + 1, above, above + 1]);
+            }
+        }
+
+        generate_tangents(&mut vertices, &indices);
+
+        for vertex in vertices.iter() {
+            let tangent = vertex.tangent;
+            assert!(
+                (tangent.x - 1.0).abs() < 1e-4 && tangent.y.abs() < 1e-4 && tangent.z.abs() < 1e-4,
+                "expected a tangent along +x, got {tangent:?}"
+            );
+        }
     }
 }
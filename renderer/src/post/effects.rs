@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use crate::{colour::RGB, texture::Bitmap};
+
+use super::{Effect, EffectInput};
+
+/// Converts linear colours for display, raising each channel to the power of `1.0 / gamma`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gamma {
+    pub gamma: f32,
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Self { gamma: 2.2 }
+    }
+}
+
+impl Effect for Gamma {
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+        let exponent = 1.0 / self.gamma;
+        for (output, colour) in output.iter_mut().zip(input.row(y)) {
+            *output = colour.map(|c| c.max(0.0).powf(exponent));
+        }
+    }
+}
+
+/// Darkens the edges of the screen. Distances are measured from the centre, where the corners are
+/// at a distance of 1.0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vignette {
+    /// How much the corners are darkened, from 0.0 for not at all to 1.0 for black
+    pub strength: f32,
+    /// The distance at which darkening starts
+    pub radius: f32,
+    /// The distance over which darkening fades in, beyond the radius
+    pub softness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            strength: 0.5,
+            radius: 0.5,
+            softness: 0.5,
+        }
+    }
+}
+
+impl Effect for Vignette {
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+        let half_width = input.width() as f32 * 0.5;
+        let half_height = input.height() as f32 * 0.5;
+        let half_diagonal_inv = 1.0 / half_width.hypot(half_height);
+        let dy = y as f32 + 0.5 - half_height;
+
+        for (x, (output, colour)) in output.iter_mut().zip(input.row(y)).enumerate() {
+            let dx = x as f32 + 0.5 - half_width;
+            let distance = dx.hypot(dy) * half_diagonal_inv;
+            let t = ((distance - self.radius) / self.softness.max(f32::EPSILON)).clamp(0.0, 1.0);
+            // smoothstep
+            let darken = t * t * (3.0 - 2.0 * t);
+
+            *output = colour.map(|c| c * (1.0 - self.strength * darken));
+        }
+    }
+}
+
+/// A 3D lookup table mapping input colours to graded output colours, with `size` entries along
+/// each axis
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lut {
+    size: usize,
+    /// Indexed by red, then green, then blue, with red varying fastest
+    colours: Vec<RGB>,
+}
+
+impl Lut {
+    /// Creates a table that leaves colours unchanged
+    pub fn identity(size: usize) -> Self {
+        assert!(size >= 2, "LUT needs at least 2 entries per axis");
+
+        let scale = 1.0 / (size - 1) as f32;
+        let mut colours = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    colours.push(RGB::new(
+                        r as f32 * scale,
+                        g as f32 * scale,
+                        b as f32 * scale,
+                    ));
+                }
+            }
+        }
+
+        Self { size, colours }
+    }
+
+    /// Loads a table from a strip of square slices laid side by side, as exported by most image
+    /// editors. Red increases to the right within each slice, green increases downwards, and blue
+    /// increases from one slice to the next
+    pub fn from_path_png(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let bitmap = Bitmap::from_path_png(path.as_ref())?;
+        let size = bitmap.height();
+        if size < 2 || bitmap.width() != size * size {
+            anyhow::bail!(
+                "LUT {:?} is {}x{}, expected a width of the height squared",
+                path.as_ref(),
+                bitmap.width(),
+                bitmap.height()
+            );
+        }
+
+        let width = bitmap.width();
+        let mut colours = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    colours.push(RGB::from(bitmap.pixels()[g * width + b * size + r]));
+                }
+            }
+        }
+
+        Ok(Self { size, colours })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Finds the graded colour, interpolating between the nearest entries of the table
+    #[inline(always)]
+    pub fn lookup(&self, colour: RGB) -> RGB {
+        let max = (self.size - 1) as f32;
+        let position = |c: f32| {
+            let c = c.clamp(0.0, 1.0) * max;
+            let i = (c as usize).min(self.size - 2);
+            (i, c - i as f32)
+        };
+        let (r, fract_r) = position(colour.r);
+        let (g, fract_g) = position(colour.g);
+        let (b, fract_b) = position(colour.b);
+
+        let entry =
+            |r: usize, g: usize, b: usize| self.colours[(b * self.size + g) * self.size + r];
+        // `a.blend(b, t)` weights `a` by `t`, so each pair is given highest first
+        let lerp_r = |g: usize, b: usize| entry(r + 1, g, b).blend(entry(r, g, b), fract_r);
+        let lerp_g = |b: usize| lerp_r(g + 1, b).blend(lerp_r(g, b), fract_g);
+
+        lerp_g(b + 1).blend(lerp_g(b), fract_b)
+    }
+}
+
+/// Grades colours with a lookup table
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColourGrading {
+    pub lut: Lut,
+}
+
+impl Effect for ColourGrading {
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+        for (output, colour) in output.iter_mut().zip(input.row(y)) {
+            *output = self.lut.lookup(*colour);
+        }
+    }
+}
+
+/// Exaggerates the difference between each pixel and its four neighbours
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sharpen {
+    pub strength: f32,
+}
+
+impl Default for Sharpen {
+    fn default() -> Self {
+        Self { strength: 0.5 }
+    }
+}
+
+impl Effect for Sharpen {
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+        let y = y as isize;
+        let strength = self.strength;
+
+        for (x, output) in output.iter_mut().enumerate() {
+            let x = x as isize;
+            let centre = input.pixel(x, y);
+            let neighbours = [
+                input.pixel(x - 1, y),
+                input.pixel(x + 1, y),
+                input.pixel(x, y - 1),
+                input.pixel(x, y + 1),
+            ];
+            let sharpen = |c: f32, channel: fn(&RGB) -> f32| {
+                let sum: f32 = neighbours.iter().map(channel).sum();
+                (c + strength * (4.0 * c - sum)).max(0.0)
+            };
+
+            *output = RGB::new(
+                sharpen(centre.r, |n| n.r),
+                sharpen(centre.g, |n| n.g),
+                sharpen(centre.b, |n| n.b),
+            );
+        }
+    }
+}
+
+/// Fast approximate anti-aliasing, which finds edges from the contrast in luminance between
+/// neighbouring pixels, then blurs along them. This works best on colours that have already been
+/// gamma corrected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fxaa {
+    /// The contrast needed to count as an edge, relative to the brightest nearby pixel
+    pub edge_threshold: f32,
+    /// The contrast below which nothing counts as an edge, which skips dark areas
+    pub edge_threshold_min: f32,
+    /// The furthest that samples are taken along an edge, in pixels
+    pub span_max: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            edge_threshold: 1.0 / 8.0,
+            edge_threshold_min: 1.0 / 16.0,
+            span_max: 8.0,
+        }
+    }
+}
+
+impl Effect for Fxaa {
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+        const REDUCE_MUL: f32 = 1.0 / 8.0;
+        const REDUCE_MIN: f32 = 1.0 / 128.0;
+
+        let yi = y as isize;
+
+        for (x, output) in output.iter_mut().enumerate() {
+            let xi = x as isize;
+            let centre = input.pixel(xi, yi);
+            let luma_m = centre.luminance();
+            let luma_nw = input.pixel(xi - 1, yi - 1).luminance();
+            let luma_ne = input.pixel(xi + 1, yi - 1).luminance();
+            let luma_sw = input.pixel(xi - 1, yi + 1).luminance();
+            let luma_se = input.pixel(xi + 1, yi + 1).luminance();
+
+            let luma_min = luma_m.min(luma_nw.min(luma_ne).min(luma_sw.min(luma_se)));
+            let luma_max = luma_m.max(luma_nw.max(luma_ne).max(luma_sw.max(luma_se)));
+            if luma_max - luma_min < self.edge_threshold_min.max(luma_max * self.edge_threshold) {
+                *output = centre;
+                continue;
+            }
+
+            // the direction along the edge, perpendicular to the gradient in luminance
+            let dir_x = -((luma_nw + luma_ne) - (luma_sw + luma_se));
+            let dir_y = (luma_nw + luma_sw) - (luma_ne + luma_se);
+
+            // scale so the shorter axis is one pixel long, without blowing up flat areas
+            let reduce =
+                ((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL).max(REDUCE_MIN);
+            let scale = 1.0 / (dir_x.abs().min(dir_y.abs()) + reduce);
+            let dir_x = (dir_x * scale).clamp(-self.span_max, self.span_max);
+            let dir_y = (dir_y * scale).clamp(-self.span_max, self.span_max);
+
+            let centre_x = x as f32 + 0.5;
+            let centre_y = y as f32 + 0.5;
+            let sample = |t: f32| input.sample(centre_x + dir_x * t, centre_y + dir_y * t);
+
+            // a narrow blur along the edge, and a wider one which may cross onto another edge
+            let narrow = sample(1.0 / 3.0 - 0.5).blend(sample(2.0 / 3.0 - 0.5), 0.5);
+            let wide = narrow.blend(sample(-0.5).blend(sample(0.5), 0.5), 0.5);
+
+            let luma_wide = wide.luminance();
+            *output = if luma_wide < luma_min || luma_wide > luma_max {
+                narrow
+            } else {
+                wide
+            };
+        }
+    }
+}
@@ -0,0 +1,265 @@
+mod effects;
+
+pub use effects::*;
+
+use crate::{colour::RGB, framebuffer::Framebuffer};
+
+/// A full screen effect, applied to the framebuffer after each frame is rendered. Rows are
+/// processed independently, in parallel when multithreaded, so effects must be `Send` and `Sync`
+pub trait Effect: Send + Sync {
+    /// Writes the processed colours of row `y` to `output`, which is as wide as the framebuffer.
+    /// The input is the output of the previous effect, and isn't changed until every row is done
+    fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]);
+}
+
+/// The colour and depth of every pixel in the framebuffer, as seen by an [Effect]
+pub struct EffectInput<'a> {
+    width: usize,
+    height: usize,
+    samples: usize,
+    pixels: &'a [RGB],
+    depth: &'a [f32],
+}
+
+impl<'a> EffectInput<'a> {
+    pub(crate) fn new(framebuffer: &'a Framebuffer) -> Self {
+        Self {
+            width: framebuffer.width(),
+            height: framebuffer.height(),
+            samples: framebuffer.samples(),
+            pixels: framebuffer.pixels(),
+            depth: framebuffer.depth(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn row(&self, y: usize) -> &[RGB] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
+    /// The colour of a pixel, where coordinates outside the framebuffer are clamped to its edges
+    #[inline(always)]
+    pub fn pixel(&self, x: isize, y: isize) -> RGB {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    /// Bilinearly samples the colour at a position in pixels, where pixel centres lie half way
+    /// between whole numbers
+    #[inline(always)]
+    pub fn sample(&self, x: f32, y: f32) -> RGB {
+        let x = x - 0.5;
+        let y = y - 0.5;
+        let x_floor = x.floor();
+        let y_floor = y.floor();
+        let fract_x = x - x_floor;
+        let fract_y = y - y_floor;
+        let x0 = x_floor as isize;
+        let y0 = y_floor as isize;
+
+        let top = self.pixel(x0 + 1, y0).blend(self.pixel(x0, y0), fract_x);
+        let bottom = self
+            .pixel(x0 + 1, y0 + 1)
+            .blend(self.pixel(x0, y0 + 1), fract_x);
+        bottom.blend(top, fract_y)
+    }
+
+    /// The view space depth of a pixel, or `f32::MAX` where nothing was drawn. With multisampling,
+    /// this is the depth of the pixel's first sample
+    #[inline(always)]
+    pub fn depth(&self, x: usize, y: usize) -> f32 {
+        self.depth[(y * self.width + x) * self.samples]
+    }
+}
+
+/// Identifies an effect added to a [Renderer](crate::Renderer)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EffectId(usize);
+
+/// An ordered list of effects, which are applied one after another
+#[derive(Default)]
+pub(crate) struct PostProcess {
+    effects: Vec<(EffectId, Box<dyn Effect>)>,
+    next_id: usize,
+    /// Each effect writes here, before it's swapped with the framebuffer's pixels
+    pub scratch: Vec<RGB>,
+}
+
+impl PostProcess {
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Inserts an effect at a position in the order, clamped to the end of the list
+    pub fn insert(&mut self, index: usize, effect: Box<dyn Effect>) -> EffectId {
+        let id = EffectId(self.next_id);
+        self.next_id += 1;
+
+        let index = index.min(self.effects.len());
+        self.effects.insert(index, (id, effect));
+        id
+    }
+
+    pub fn remove(&mut self, id: EffectId) -> Option<Box<dyn Effect>> {
+        let index = self.index_of(id)?;
+        Some(self.effects.remove(index).1)
+    }
+
+    /// Moves an effect to a new position in the order, clamped to the end of the list. Returns
+    /// `false` if there's no such effect
+    pub fn reorder(&mut self, id: EffectId, index: usize) -> bool {
+        let Some(from) = self.index_of(id) else {
+            return false;
+        };
+
+        let effect = self.effects.remove(from);
+        let index = index.min(self.effects.len());
+        self.effects.insert(index, effect);
+        true
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = EffectId> + '_ {
+        self.effects.iter().map(|(id, _)| *id)
+    }
+
+    pub fn effects(&self) -> impl Iterator<Item = &dyn Effect> {
+        self.effects.iter().map(|(_, effect)| effect.as_ref())
+    }
+
+    fn index_of(&self, id: EffectId) -> Option<usize> {
+        self.effects
+            .iter()
+            .position(|(effect_id, _)| *effect_id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        colour::RGB,
+        framebuffer::Framebuffer,
+        renderer::Renderer,
+        testing::{assert_near, test_config},
+    };
+
+    use super::{Effect, EffectInput, Fxaa, Gamma, Lut};
+
+    /// Multiplies every channel by a factor, then adds an offset
+    struct Affine {
+        scale: f32,
+        offset: f32,
+    }
+
+    impl Effect for Affine {
+        fn apply_row(&self, input: &EffectInput, y: usize, output: &mut [RGB]) {
+            for (output, colour) in output.iter_mut().zip(input.row(y)) {
+                *output = colour.map(|c| c * self.scale + self.offset);
+            }
+        }
+    }
+
+    /// Applies an effect to a framebuffer filled by `colour`, returning the processed pixels
+    fn apply(effect: &dyn Effect, width: usize, colour: impl Fn(usize, usize) -> RGB) -> Vec<RGB> {
+        let mut framebuffer = Framebuffer::new(width, width, 1);
+        for (i, pixel) in framebuffer.pixels_mut().iter_mut().enumerate() {
+            *pixel = colour(i % width, i / width);
+        }
+
+        let input = EffectInput::new(&framebuffer);
+        let mut output = vec![RGB::default(); width * width];
+        for (y, row) in output.chunks_exact_mut(width).enumerate() {
+            effect.apply_row(&input, y, row);
+        }
+
+        output
+    }
+
+    #[test]
+    fn effects_apply_in_order() {
+        let mut renderer = Renderer::new(16, 12, 90.0, test_config()).unwrap();
+        renderer.set_clear_colour(RGB::new(0.5, 0.5, 0.5));
+        let render = |renderer: &mut Renderer| {
+            renderer.render();
+            renderer.pixels().iter().for_each(|&pixel| {
+                assert_eq!(pixel, renderer.pixels()[0]);
+            });
+            renderer.pixels()[0].r
+        };
+
+        let half = renderer.add_effect(Affine {
+            scale: 0.5,
+            offset: 0.0,
+        });
+        let brighten = renderer.add_effect(Affine {
+            scale: 1.0,
+            offset: 0.25,
+        });
+        assert_eq!(render(&mut renderer), 0.5);
+
+        assert!(renderer.reorder_effect(brighten, 0));
+        assert_eq!(renderer.effects().collect::<Vec<_>>(), [brighten, half]);
+        assert_eq!(render(&mut renderer), 0.375);
+
+        assert!(renderer.remove_effect(brighten).is_some());
+        assert!(!renderer.reorder_effect(brighten, 0));
+        assert_eq!(render(&mut renderer), 0.25);
+
+        assert!(renderer.remove_effect(half).is_some());
+        assert_eq!(render(&mut renderer), 0.5);
+    }
+
+    #[test]
+    fn effects_skip_empty_framebuffer() {
+        let mut renderer = Renderer::new(16, 12, 90.0, test_config()).unwrap();
+        renderer.add_effect(Gamma { gamma: 2.2 });
+
+        // a minimised window, for example
+        renderer.update_viewport(0, 12);
+        renderer.render();
+        assert!(renderer.pixels().is_empty());
+    }
+
+    #[test]
+    fn gamma_and_identity_lut() {
+        let colour = RGB::new(0.25, 0.5, 0.75);
+
+        let pixels = apply(&Gamma { gamma: 2.0 }, 4, |_, _| colour);
+        assert_near(
+            pixels[0],
+            RGB::new(0.5, 0.5f32.sqrt(), 0.75f32.sqrt()),
+            1e-5,
+        );
+
+        let graded = Lut::identity(5).lookup(RGB::new(0.3, 0.6, 0.9));
+        assert_near(graded, RGB::new(0.3, 0.6, 0.9), 1e-5);
+    }
+
+    #[test]
+    fn fxaa_smooths_edges_only() {
+        let fxaa = Fxaa::default();
+
+        let flat = apply(&fxaa, 8, |_, _| RGB::new(0.5, 0.5, 0.5));
+        assert!(flat.iter().all(|&pixel| pixel == RGB::new(0.5, 0.5, 0.5)));
+
+        // a staircase along the diagonal
+        let diagonal = |x: usize, y: usize| if x > y { RGB::WHITE } else { RGB::BLACK };
+        let smoothed = apply(&fxaa, 8, diagonal);
+        let blended = smoothed
+            .iter()
+            .filter(|pixel| pixel.r > 0.01 && pixel.r < 0.99)
+            .count();
+        assert!(blended > 0);
+
+        // pixels away from the edge are unchanged
+        assert_eq!(smoothed[7], RGB::WHITE);
+        assert_eq!(smoothed[7 * 8], RGB::BLACK);
+    }
+}
@@ -0,0 +1,377 @@
+use maths::{
+    geometry::Triangle,
+    linear::{Vec2f, Vec3f},
+};
+
+use crate::config::Msaa;
+
+/// Number of fractional bits in the fixed point vertex positions. Snapping vertices to a sub-pixel
+/// grid makes the edge functions exact, so an edge shared by two triangles gives exactly opposite
+/// values for each of them
+const SUBPIXEL_BITS: u32 = 8;
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+const SUBPIXEL_HALF: i64 = SUBPIXEL_ONE / 2;
+
+/// Fixed point positions are clamped to this range (about two million pixels either side of the
+/// origin), so that evaluating the edge functions can't overflow
+const GUARD_BAND: i64 = 1 << (21 + SUBPIXEL_BITS);
+
+/// A single edge of a triangle, as a function of the pixel position which is positive on the
+/// inside of the triangle
+#[derive(Clone, Copy, Debug, Default)]
+struct Edge {
+    /// Change in value for a step of one pixel along the horizontal axis
+    step_x: i64,
+    /// Change in value for a step of one pixel along the vertical axis
+    step_y: i64,
+    /// Value at the centre of the pixel (0, 0)
+    origin: i64,
+    /// Added to the value before the inside test, as 0 for top and left edges and -1 otherwise. This
+    /// implements the top-left fill rule, where pixels exactly on an edge only belong to the
+    /// triangle if the edge is a top or left edge
+    bias: i64,
+}
+
+impl Edge {
+    /// Creates the edge from `from` to `to`, with `sign` flipping the function so that it's
+    /// positive on the inside regardless of the winding order
+    fn new(from: [i64; 2], to: [i64; 2], sign: i64) -> Self {
+        let dx = (to[0] - from[0]) * sign;
+        let dy = (to[1] - from[1]) * sign;
+
+        // With the y axis pointing down, and the inside to the right of the edge, a top edge is
+        // horizontal and points right, and a left edge points up
+        let top_left = dy < 0 || (dy == 0 && dx > 0);
+
+        Self {
+            step_x: -dy * SUBPIXEL_ONE,
+            step_y: dx * SUBPIXEL_ONE,
+            origin: dx * (SUBPIXEL_HALF - from[1]) - dy * (SUBPIXEL_HALF - from[0]),
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+}
+
+/// The values of a triangle's edge functions at a single pixel. These are proportional to the
+/// pixel's barycentric coordinates
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EdgeValues([i64; 3]);
+
+impl EdgeValues {
+    #[inline(always)]
+    fn offset(&self, offset: &EdgeValues) -> EdgeValues {
+        EdgeValues([
+            self.0[0] + offset.0[0],
+            self.0[1] + offset.0[1],
+            self.0[2] + offset.0[2],
+        ])
+    }
+}
+
+/// Edge functions for rasterising a triangle, evaluated incrementally across a tile with integer
+/// arithmetic. The edges are opposite vertices a, b and c in that order, so that the values
+/// weight the vertices in the same order as barycentric coordinates
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EdgeFunctions {
+    edges: [Edge; 3],
+    area_inv: f32,
+}
+
+impl EdgeFunctions {
+    pub fn new(triangle: &Triangle<Vec2f>) -> Self {
+        let a = snap(triangle.a);
+        let b = snap(triangle.b);
+        let c = snap(triangle.c);
+
+        let area = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+        if area == 0 {
+            return Self::default();
+        }
+
+        let sign = area.signum();
+
+        Self {
+            edges: [
+                Edge::new(b, c, sign),
+                Edge::new(c, a, sign),
+                Edge::new(a, b, sign),
+            ],
+            area_inv: 1.0 / (area * sign) as f32,
+        }
+    }
+
+    /// Whether the triangle covers no area once snapped to the sub-pixel grid, in which case it
+    /// should not be drawn
+    pub fn is_degenerate(&self) -> bool {
+        self.area_inv == 0.0
+    }
+
+    /// Evaluates the edge functions at the centre of the pixel (x, y)
+    #[inline(always)]
+    pub fn values_at(&self, x: usize, y: usize) -> EdgeValues {
+        let (x, y) = (x as i64, y as i64);
+        EdgeValues(self.edges.map(|e| e.origin + e.step_x * x + e.step_y * y))
+    }
+
+    /// Moves the values one pixel to the right
+    #[inline(always)]
+    pub fn step_x(&self, values: &mut EdgeValues) {
+        values.0[0] += self.edges[0].step_x;
+        values.0[1] += self.edges[1].step_x;
+        values.0[2] += self.edges[2].step_x;
+    }
+
+    /// Moves the values one pixel down
+    #[inline(always)]
+    pub fn step_y(&self, values: &mut EdgeValues) {
+        values.0[0] += self.edges[0].step_y;
+        values.0[1] += self.edges[1].step_y;
+        values.0[2] += self.edges[2].step_y;
+    }
+
+    /// Whether the pixel is covered by the triangle, following the top-left fill rule
+    #[inline(always)]
+    pub fn is_inside(&self, values: &EdgeValues) -> bool {
+        // The sign bit of the combined values is set if any of them are negative
+        ((values.0[0] + self.edges[0].bias)
+            | (values.0[1] + self.edges[1].bias)
+            | (values.0[2] + self.edges[2].bias))
+            >= 0
+    }
+
+    /// Calculates the change in value from a pixel's centre to each sample position, given as
+    /// offsets in pixels. Positions are snapped to the sub-pixel grid, so the offsets are exact
+    pub fn sample_offsets(&self, positions: &[Vec2f]) -> [EdgeValues; Msaa::MAX_SAMPLES] {
+        let mut offsets = [EdgeValues::default(); Msaa::MAX_SAMPLES];
+
+        for (offset, &position) in offsets.iter_mut().zip(positions) {
+            let [x, y] = snap(position);
+            // The steps are multiples of a whole pixel, so the shift is exact
+            *offset = EdgeValues(
+                self.edges
+                    .map(|e| (e.step_x * x + e.step_y * y) >> SUBPIXEL_BITS),
+            );
+        }
+
+        offsets
+    }
+
+    /// Tests each sample of a pixel against the triangle, returning a mask with a bit set for each
+    /// covered sample
+    #[inline(always)]
+    pub fn coverage(&self, values: &EdgeValues, sample_offsets: &[EdgeValues]) -> u32 {
+        let mut coverage = 0;
+        for (sample, offset) in sample_offsets.iter().enumerate() {
+            coverage |= (self.is_inside(&values.offset(offset)) as u32) << sample;
+        }
+        coverage
+    }
+
+    /// Change in barycentric coordinates for a step of one pixel to the right
+    #[cfg(feature = "simd")]
+    pub fn barycentric_step_x(&self) -> Vec3f {
+        Vec3f::new(
+            self.edges[0].step_x as f32 * self.area_inv,
+            self.edges[1].step_x as f32 * self.area_inv,
+            self.edges[2].step_x as f32 * self.area_inv,
+        )
+    }
+
+    /// Tests four consecutive pixels against the triangle, starting from the given values, returning
+    /// a mask with a bit set for each covered pixel
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    pub fn coverage_x4(&self, values: &EdgeValues) -> u32 {
+        let mut values = *values;
+        let mut coverage = 0;
+        for lane in 0..4 {
+            coverage |= (self.is_inside(&values) as u32) << lane;
+            self.step_x(&mut values);
+        }
+        coverage
+    }
+
+    #[inline(always)]
+    pub fn barycentric(&self, values: &EdgeValues) -> Vec3f {
+        Vec3f::new(
+            values.0[0] as f32 * self.area_inv,
+            values.0[1] as f32 * self.area_inv,
+            values.0[2] as f32 * self.area_inv,
+        )
+    }
+}
+
+#[inline(always)]
+fn snap(point: Vec2f) -> [i64; 2] {
+    let snap_axis = |x: f32| {
+        // NaN saturates to 0
+        ((x * SUBPIXEL_ONE as f32).round() as i64).clamp(-GUARD_BAND, GUARD_BAND)
+    };
+
+    [snap_axis(point.x), snap_axis(point.y)]
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::{geometry::Triangle, linear::Vec2f};
+
+    use super::EdgeFunctions;
+
+    const SIZE: usize = 64;
+
+    /// Rasterises each triangle across the whole grid, counting the number of times each pixel is
+    /// covered
+    fn coverage(triangles: &[Triangle<Vec2f>]) -> Vec<u32> {
+        let mut counts = vec![0; SIZE * SIZE];
+
+        for triangle in triangles {
+            let edges = EdgeFunctions::new(triangle);
+            if edges.is_degenerate() {
+                continue;
+            }
+
+            let mut row = edges.values_at(0, 0);
+            for y in 0..SIZE {
+                let mut values = row;
+                for x in 0..SIZE {
+                    if edges.is_inside(&values) {
+                        counts[y * SIZE + x] += 1;
+                    }
+                    edges.step_x(&mut values);
+                }
+                edges.step_y(&mut row);
+            }
+        }
+
+        counts
+    }
+
+    /// Splits each cell of a square grid of points into two triangles, alternating the diagonal
+    fn grid_mesh(points: &[Vec2f], cells: usize, clockwise: bool) -> Vec<Triangle<Vec2f>> {
+        let point = |x: usize, y: usize| points[y * (cells + 1) + x];
+        let mut triangles = Vec::new();
+
+        for y in 0..cells {
+            for x in 0..cells {
+                let (tl, tr) = (point(x, y), point(x + 1, y));
+                let (bl, br) = (point(x, y + 1), point(x + 1, y + 1));
+
+                let pair = if (x + y) % 2 == 0 {
+                    [[tl, tr, br], [br, bl, tl]]
+                } else {
+                    [[tl, tr, bl], [tr, br, bl]]
+                };
+
+                for [a, b, c] in pair {
+                    let vertices = if clockwise { [a, b, c] } else { [a, c, b] };
+                    triangles.push(Triangle::from(vertices));
+                }
+            }
+        }
+
+        triangles
+    }
+
+    fn grid_points(cells: usize, min: f32, cell_size: f32) -> Vec<Vec2f> {
+        let mut points = Vec::new();
+        for y in 0..=cells {
+            for x in 0..=cells {
+                points.push(Vec2f::new(
+                    min + x as f32 * cell_size,
+                    min + y as f32 * cell_size,
+                ));
+            }
+        }
+        points
+    }
+
+    /// Asserts that pixels with centres in the half open square `min..max` are covered exactly
+    /// once, and all others are not covered
+    fn assert_covered_once(counts: &[u32], min: f32, max: f32) {
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+                let expected = (min <= cx && cx < max && min <= cy && cy < max) as u32;
+
+                assert_eq!(
+                    counts[y * SIZE + x],
+                    expected,
+                    "pixel ({x}, {y}) covered {} times",
+                    counts[y * SIZE + x],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grid_through_pixel_centres() {
+        // Every vertex lies on a pixel centre, so every edge passes through pixel centres
+        let points = grid_points(7, 4.5, 8.0);
+
+        for clockwise in [true, false] {
+            let counts = coverage(&grid_mesh(&points, 7, clockwise));
+            assert_covered_once(&counts, 4.5, 60.5);
+        }
+    }
+
+    #[test]
+    fn jittered_grid() {
+        let cells = 10;
+        let mut points = grid_points(cells, 2.0, 6.0);
+
+        // Move the interior points by a pseudo-random amount, keeping the outline square
+        let mut seed = 0x2545_f491_u32;
+        let mut random = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed as f32 / u32::MAX as f32 - 0.5) * 2.0
+        };
+
+        for y in 1..cells {
+            for x in 1..cells {
+                let point = &mut points[y * (cells + 1) + x];
+                point.x += random();
+                point.y += random();
+            }
+        }
+
+        for clockwise in [true, false] {
+            let counts = coverage(&grid_mesh(&points, cells, clockwise));
+            assert_covered_once(&counts, 2.0, 62.0);
+        }
+    }
+
+    #[test]
+    fn fan_around_pixel_centre() {
+        let centre = Vec2f::new(32.5, 32.5);
+        let outline = [
+            Vec2f::new(8.5, 8.5),
+            Vec2f::new(32.5, 8.5),
+            Vec2f::new(56.5, 8.5),
+            Vec2f::new(56.5, 20.25),
+            Vec2f::new(56.5, 56.5),
+            Vec2f::new(32.5, 56.5),
+            Vec2f::new(8.5, 56.5),
+            Vec2f::new(8.5, 41.7),
+        ];
+
+        let triangles: Vec<_> = (0..outline.len())
+            .map(|i| Triangle::from([centre, outline[i], outline[(i + 1) % outline.len()]]))
+            .collect();
+
+        assert_covered_once(&coverage(&triangles), 8.5, 56.5);
+    }
+
+    #[test]
+    fn degenerate_triangle_covers_nothing() {
+        let triangle = Triangle::from([
+            Vec2f::new(4.5, 4.5),
+            Vec2f::new(20.5, 20.5),
+            Vec2f::new(40.5, 40.5),
+        ]);
+
+        assert!(EdgeFunctions::new(&triangle).is_degenerate());
+        assert!(coverage(&[triangle]).iter().all(|&count| count == 0));
+    }
+}
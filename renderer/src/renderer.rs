@@ -1,6 +1,7 @@
 use std::{
     marker::PhantomData,
-    time::{SystemTime, UNIX_EPOCH},
+    ops::Range,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use collections::SparseMap;
@@ -8,24 +9,50 @@ use maths::{
     geometry::AABB,
     linear::{Mat4f, Vec3f},
 };
+#[cfg(feature = "multithreaded")]
+use rayon::prelude::*;
 
 use crate::{
-    asset_manager::AssetManager,
+    asset_manager::{AssetId, AssetManager},
+    camera::{Projection, View},
     colour::RGB,
+    config::RenderConfig,
+    light::{Light, Lighting, ViewLight},
     line::LineRenderer,
-    model::{Mesh, Model, ProjectedTriangle},
+    model::{Mesh, MeshInstance, Model, ProjectedTriangle},
+    post::{Effect, EffectId, EffectInput, PostProcess},
+    shader::Uniforms,
+    shadow::ShadowMap,
     shapes::{unit_cube_mesh, unit_sphere_mesh},
-    texture::Texture,
+    skybox::draw_skybox,
+    texture::{Cubemap, Texture},
     tile::TileRenderer,
     util::{focal_dimensions, view_frustum_bounds},
-    RES_SCALE,
 };
 
 use super::{camera::Camera, framebuffer::Framebuffer};
 
+/// The number of triangles projected together as a single unit of work
+const PROJECTION_CHUNK: usize = 1024;
+
+/// A range of the triangles of a mesh instance, projected together
+type Chunk<'a> = (&'a Mesh, &'a MeshInstance, Range<usize>);
+
 pub struct RendererState {
     pub framebuffer: Framebuffer,
     pub camera: Camera,
+    pub config: RenderConfig,
+    pub uniforms: Uniforms,
+    /// Per vertex lighting, or `None` to leave vertex colours unlit
+    pub lighting: Option<Lighting>,
+    /// Lights used for per pixel lighting this frame, in view space
+    pub(crate) lights: Vec<ViewLight>,
+    /// Depth seen from the shadow casting light this frame, or `None` if no light casts shadows
+    pub(crate) shadow_map: Option<ShadowMap>,
+    /// Drawn behind everything, in place of the clear colour
+    skybox: Option<AssetId<Cubemap>>,
+    viewport_width: usize,
+    viewport_height: usize,
     view_frustum_bounds: AABB<Vec3f>,
     h_fov_rad: f32,
     v_fov_rad: f32,
@@ -66,24 +93,61 @@ impl RendererState {
     pub fn focal_height(&self) -> f32 {
         self.focal_height
     }
+
+    /// The colour that fog blends towards this frame
+    pub(crate) fn fog_colour(&self) -> RGB {
+        self.config.fog_colour.unwrap_or(self.clear_colour)
+    }
+
+    /// The camera's perspective view, projected onto the framebuffer
+    pub(crate) fn camera_view(&self) -> View {
+        View {
+            transform: *self.camera.view_transform(),
+            projection: Projection::Perspective {
+                focal_width: self.focal_width,
+                focal_height: self.focal_height,
+            },
+            half_width: self.framebuffer.half_width(),
+            half_height: self.framebuffer.half_height(),
+            near: self.config.near,
+            far: self.config.far,
+        }
+    }
 }
 
 pub struct Renderer {
     state: RendererState,
     tile_renderer: TileRenderer,
+    /// Draws the shadow map, on the same threads as the main tile renderer
+    shadow_renderer: TileRenderer,
     line_renderer: LineRenderer,
+    /// Effects applied to the framebuffer at the end of each frame, in order
+    post_process: PostProcess,
 
     assets: AssetManager,
 
     projected_triangles: Vec<ProjectedTriangle>,
+    blended_triangles: Vec<ProjectedTriangle>,
+    shadow_triangles: Vec<ProjectedTriangle>,
+
+    start_time: Instant,
 }
 
 impl Renderer {
-    pub fn new(width: usize, height: usize, horiz_fov: f32) -> Self {
-        let tile_renderer = TileRenderer::default();
+    /// Fails if the pool of worker threads can't be created
+    pub fn new(
+        width: usize,
+        height: usize,
+        horiz_fov: f32,
+        mut config: RenderConfig,
+    ) -> Result<Self, anyhow::Error> {
+        config.clamp_tile_size();
+        let tile_renderer = TileRenderer::new(&config)?;
+        let mut shadow_renderer = TileRenderer::unthreaded(&config);
+        shadow_renderer.share_threads(&tile_renderer);
         let line_renderer = LineRenderer::default();
 
-        let framebuffer = Framebuffer::new(width, height);
+        let framebuffer = Framebuffer::new(width, height, config.msaa.samples());
         let camera = Camera::new();
         let h_fov_rad = horiz_fov.to_radians();
         let v_fov_rad = h_fov_rad / framebuffer.aspect_ratio();
@@ -93,11 +157,19 @@ impl Renderer {
             framebuffer.half_width(),
             framebuffer.half_height(),
         );
-        let view_frustum_bounds = view_frustum_bounds(h_fov_rad, v_fov_rad);
+        let view_frustum_bounds = view_frustum_bounds(h_fov_rad, v_fov_rad, &config);
 
         let state = RendererState {
             framebuffer,
             camera,
+            config,
+            uniforms: Uniforms::default(),
+            lighting: None,
+            lights: Vec::new(),
+            shadow_map: None,
+            skybox: None,
+            viewport_width: width,
+            viewport_height: height,
             view_frustum_bounds,
             h_fov_rad,
             v_fov_rad,
@@ -107,17 +179,27 @@ impl Renderer {
         };
 
         let projected_triangles = Vec::new();
+        let blended_triangles = Vec::new();
         let assets = AssetManager::new();
 
-        Self {
+        let mut renderer = Self {
             state,
             tile_renderer,
+            shadow_renderer,
             line_renderer,
+            post_process: PostProcess::default(),
 
             assets,
 
             projected_triangles,
-        }
+            blended_triangles,
+            shadow_triangles: Vec::new(),
+
+            start_time: Instant::now(),
+        };
+        renderer.update_viewport(width, height);
+
+        Ok(renderer)
     }
 
     pub fn internal_width(&self) -> usize {
@@ -149,33 +231,145 @@ impl Renderer {
         &mut self.assets
     }
 
+    pub fn config(&self) -> &RenderConfig {
+        &self.state.config
+    }
+
+    /// Replaces the current configuration, taking effect from the next call to [Renderer::render].
+    /// Fails if the pool of worker threads can't be rebuilt, in which case the current
+    /// configuration is kept
+    pub fn set_config(&mut self, mut config: RenderConfig) -> Result<(), anyhow::Error> {
+        config.clamp_tile_size();
+        if self.state.config.threads != config.threads {
+            self.tile_renderer.set_threads(config.threads)?;
+            self.shadow_renderer.share_threads(&self.tile_renderer);
+        }
+
+        let old_config = std::mem::replace(&mut self.state.config, config);
+        let config = &self.state.config;
+
+        // the shadow map is recreated, along with its tiles, when next drawn
+        if old_config.tile_width != config.tile_width
+            || old_config.tile_height != config.tile_height
+        {
+            self.state.shadow_map = None;
+        }
+
+        if old_config.res_scale != config.res_scale
+            || old_config.tile_width != config.tile_width
+            || old_config.tile_height != config.tile_height
+            || old_config.msaa != config.msaa
+        {
+            self.update_viewport(self.state.viewport_width, self.state.viewport_height);
+        } else if old_config.near != config.near || old_config.far != config.far {
+            self.state.view_frustum_bounds = view_frustum_bounds(
+                self.state.h_fov_rad,
+                self.state.v_fov_rad,
+                &self.state.config,
+            );
+        }
+
+        Ok(())
+    }
+
     pub fn set_clear_colour(&mut self, colour: RGB) {
         self.state.clear_colour = colour;
     }
 
+    /// Sets the cubemap drawn behind everything, or `None` to fill the background with the clear
+    /// colour
+    pub fn set_skybox(&mut self, cubemap_id: Option<AssetId<Cubemap>>) {
+        self.state.skybox = cubemap_id;
+    }
+
+    /// Adds a post processing effect, to be applied after all the others
+    pub fn add_effect(&mut self, effect: impl Effect + 'static) -> EffectId {
+        self.post_process.insert(usize::MAX, Box::new(effect))
+    }
+
+    /// Adds a post processing effect at a position in the order, or at the end if the position is
+    /// past the last effect
+    pub fn insert_effect(&mut self, index: usize, effect: impl Effect + 'static) -> EffectId {
+        self.post_process.insert(index, Box::new(effect))
+    }
+
+    pub fn remove_effect(&mut self, effect_id: EffectId) -> Option<Box<dyn Effect>> {
+        self.post_process.remove(effect_id)
+    }
+
+    /// Moves a post processing effect to a position in the order, or to the end if the position is
+    /// past the last effect. Returns `false` if there's no such effect
+    pub fn reorder_effect(&mut self, effect_id: EffectId, index: usize) -> bool {
+        self.post_process.reorder(effect_id, index)
+    }
+
+    /// The post processing effects, in the order they're applied
+    pub fn effects(&self) -> impl Iterator<Item = EffectId> + '_ {
+        self.post_process.ids()
+    }
+
+    /// Sets the light applied to vertex colours, or `None` to leave them unlit
+    pub fn set_lighting(&mut self, lighting: Option<Lighting>) {
+        self.state.lighting = lighting;
+    }
+
     pub fn render(&mut self) {
+        self.state.uniforms.time = self.start_time.elapsed().as_secs_f32();
+        self.update_lights();
+        self.render_shadow_map();
+
         self.state
             .framebuffer
             .clear_colour_buffer(self.state.clear_colour);
         self.state.framebuffer.clear_depth_buffer();
+        self.tile_renderer.clear_depth();
 
         self.project_meshes();
 
-        self.tile_renderer.render(
-            &mut self.state,
-            &self.assets.textures,
-            &self.projected_triangles,
-        );
+        self.tile_renderer
+            .render(&mut self.state, &self.assets, &self.projected_triangles);
+
+        // The sky fills whatever the opaque geometry didn't cover, so that it shows through
+        // blended triangles
+        if let Some(cubemap) = self
+            .state
+            .skybox
+            .and_then(|id| self.assets.cubemaps.get(id))
+        {
+            let view = self.state.camera_view();
+            let camera_to_world = self.state.camera.inverse_view_transform();
+            draw_skybox(
+                &mut self.state.framebuffer,
+                cubemap,
+                &view,
+                &camera_to_world,
+                &self.state.config,
+            );
+        }
+
+        // Blended triangles are drawn over the opaque geometry, from back to front. Binning
+        // preserves this order within each tile
+        self.blended_triangles
+            .sort_by(|a, b| b.mean_depth().total_cmp(&a.mean_depth()));
+        self.tile_renderer
+            .render(&mut self.state, &self.assets, &self.blended_triangles);
+
+        self.state.framebuffer.resolve();
+        self.apply_effects();
         // self.line_renderer
         //     .render(&mut self.state, &self.projected_triangles, RGB::WHITE);
     }
 
     pub fn update_viewport(&mut self, width: usize, height: usize) {
-        let width = (width as f32 * RES_SCALE).round() as usize;
-        let height = (height as f32 * RES_SCALE).round() as usize;
+        self.state.viewport_width = width;
+        self.state.viewport_height = height;
+
+        let width = (width as f32 * self.state.config.res_scale).round() as usize;
+        let height = (height as f32 * self.state.config.res_scale).round() as usize;
 
-        self.state.framebuffer = Framebuffer::new(width, height);
-        self.tile_renderer.update_viewport(width, height);
+        self.state.framebuffer = Framebuffer::new(width, height, self.state.config.msaa.samples());
+        self.tile_renderer
+            .update_viewport(width, height, &self.state.config);
         self.line_renderer.update_viewport(width, height);
 
         self.state.v_fov_rad = self.state.h_fov_rad / self.state.framebuffer.aspect_ratio();
@@ -185,30 +379,268 @@ impl Renderer {
             self.state.framebuffer.half_width(),
             self.state.framebuffer.half_height(),
         );
-        self.state.view_frustum_bounds =
-            view_frustum_bounds(self.state.h_fov_rad, self.state.v_fov_rad);
+        self.state.view_frustum_bounds = view_frustum_bounds(
+            self.state.h_fov_rad,
+            self.state.v_fov_rad,
+            &self.state.config,
+        );
+    }
+
+    /// Transforms the lights into view space for this frame, keeping at most the configured number.
+    /// The first light that casts shadows is the one the shadow map is drawn from
+    fn update_lights(&mut self) {
+        let view_transform = self.state.camera.view_transform();
+        let lights = &mut self.state.lights;
+        let mut shadowed = false;
+
+        lights.clear();
+        lights.extend(self.assets.lights.values().map(|light| {
+            let casts_shadows = !shadowed && light.shadow_direction().is_some();
+            shadowed |= casts_shadows;
+            light.to_view(view_transform, casts_shadows)
+        }));
+
+        if lights.len() > self.state.config.max_lights {
+            lights.sort_by(|a, b| a.priority().total_cmp(&b.priority()));
+            lights.truncate(self.state.config.max_lights);
+        }
+    }
+
+    /// Draws the depth of every mesh instance from the view of the first light that casts shadows,
+    /// or drops the shadow map if there is no such light among the lights kept for this frame
+    fn render_shadow_map(&mut self) {
+        let shadowed = self.state.lights.iter().any(ViewLight::is_shadowed);
+        let Some(direction) = self
+            .assets
+            .lights
+            .values()
+            .find_map(Light::shadow_direction)
+            .filter(|_| shadowed)
+        else {
+            self.state.shadow_map = None;
+            return;
+        };
+
+        let size = self.state.config.shadow_map_size;
+        if !matches!(&self.state.shadow_map, Some(shadow_map) if shadow_map.size() == size) {
+            self.state.shadow_map = Some(ShadowMap::new(size));
+            self.shadow_renderer
+                .update_viewport(size, size, &self.state.config);
+        }
+
+        let state = &mut self.state;
+        let Some(shadow_map) = &mut state.shadow_map else {
+            return;
+        };
+        shadow_map.update(direction, &state.camera, &state.config);
+        let view = *shadow_map.view();
+
+        // Instances outside the camera's view can still cast shadows into it, so every instance is
+        // projected, and back faces are kept so that open meshes still cast shadows
+        let mut chunks = Vec::new();
+        for mesh in self.assets.meshes.values() {
+            for instance in mesh.instances.values() {
+                push_chunks(&mut chunks, mesh, instance);
+            }
+        }
+
+        // blended triangles cast shadows too, as the depth pass draws them as though they're opaque
+        self.shadow_triangles.clear();
+        for (projected, blended) in self.project_chunks(&chunks, &view, false, true) {
+            self.shadow_triangles.extend(projected);
+            self.shadow_triangles.extend(blended);
+        }
+
+        let Some(shadow_map) = &mut self.state.shadow_map else {
+            return;
+        };
+        self.shadow_renderer.clear_depth();
+        self.shadow_renderer.render_depth(
+            shadow_map.framebuffer_mut(),
+            &self.state.config,
+            &self.shadow_triangles,
+        );
+        shadow_map.resolve();
     }
 
     fn project_meshes(&mut self) {
         self.projected_triangles.clear();
+        self.blended_triangles.clear();
+
         for mesh in self.assets.meshes.values_mut() {
             mesh.update_all_view_bounds(self.state.camera.view_transform());
+        }
 
-            for (i, instance) in mesh.instances.values().into_iter().enumerate() {
+        let mut chunks = Vec::new();
+        for mesh in self.assets.meshes.values() {
+            for instance in mesh.instances.values() {
                 // skip instance if bounding box is not in view frustum
                 if self
                     .state
                     .view_frustum_bounds
                     .intersects(instance.view_bounds())
                 {
-                    for triangle in mesh.iter_instance_triangles(&self.state, i) {
-                        // skip triangle if back facing
-                        if !triangle.is_back_facing() {
-                            self.projected_triangles.push(triangle);
-                        }
-                    }
+                    push_chunks(&mut chunks, mesh, instance);
+                }
+            }
+        }
+
+        let view = self.state.camera_view();
+        for (projected, blended) in self.project_chunks(&chunks, &view, true, false) {
+            self.projected_triangles.extend(projected);
+            self.blended_triangles.extend(blended);
+        }
+    }
+
+    /// Projects chunks of triangles onto a view, returning the opaque and blended triangles of each
+    /// chunk. Back faces are culled if `cull_back_faces` is set, unless the material is double
+    /// sided. With `depth_only` set, only vertex positions are projected, as moved by vertex
+    /// shaders, without lighting. Chunks are projected independently, in parallel when multithreaded, and
+    /// returned in order, so the output doesn't depend on how the work is scheduled
+    fn project_chunks(
+        &self,
+        chunks: &[Chunk],
+        view: &View,
+        cull_back_faces: bool,
+        depth_only: bool,
+    ) -> Vec<(Vec<ProjectedTriangle>, Vec<ProjectedTriangle>)> {
+        let state = &self.state;
+        let shaders = &self.assets.shaders;
+        let materials = &self.assets.materials;
+        let project_chunk = |(mesh, instance, triangles): &Chunk| {
+            let mut projected = Vec::new();
+            let mut blended = Vec::new();
+
+            let vertex_shader = mesh
+                .shader_id
+                .and_then(|id| shaders.get(id))
+                .and_then(|shader| shader.vertex.as_deref());
+            let double_sided = mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .is_some_and(|material| material.double_sided);
+            let cull_back_faces = cull_back_faces && !double_sided;
+
+            for triangle in mesh.iter_instance_triangles(
+                state,
+                view,
+                instance,
+                vertex_shader,
+                depth_only,
+                triangles.clone(),
+            ) {
+                // skip triangle if back facing, or has no area
+                if (cull_back_faces && triangle.is_back_facing()) || triangle.is_degenerate() {
+                    continue;
+                }
+
+                if triangle.is_blended() {
+                    blended.push(triangle);
+                } else {
+                    projected.push(triangle);
+                }
+            }
+
+            (projected, blended)
+        };
+
+        #[cfg(feature = "multithreaded")]
+        return match self.tile_renderer.thread_pool() {
+            Some(pool) => pool.install(|| chunks.par_iter().map(project_chunk).collect()),
+            None => chunks.iter().map(project_chunk).collect(),
+        };
+
+        #[cfg(not(feature = "multithreaded"))]
+        chunks.iter().map(project_chunk).collect()
+    }
+
+    /// Runs each post processing effect over the resolved pixels in turn. Each effect reads the
+    /// output of the previous one, and processes bands of rows as tall as a tile, in parallel when
+    /// multithreaded
+    fn apply_effects(&mut self) {
+        if self.post_process.is_empty() {
+            return;
+        }
+
+        let framebuffer = &mut self.state.framebuffer;
+        let width = framebuffer.width();
+        if width == 0 {
+            return;
+        }
+        let band_len = width * self.state.config.tile_height;
+
+        let mut scratch = std::mem::take(&mut self.post_process.scratch);
+        scratch.resize(framebuffer.pixels().len(), RGB::default());
+
+        for effect in self.post_process.effects() {
+            let input = EffectInput::new(framebuffer);
+            let apply_band = |(band, pixels): (usize, &mut [RGB])| {
+                let first_row = band * band_len / width;
+                for (row, pixels) in pixels.chunks_exact_mut(width).enumerate() {
+                    effect.apply_row(&input, first_row + row, pixels);
                 }
+            };
+
+            #[cfg(feature = "multithreaded")]
+            match self.tile_renderer.thread_pool() {
+                Some(pool) => pool.install(|| {
+                    scratch
+                        .par_chunks_mut(band_len)
+                        .enumerate()
+                        .for_each(apply_band)
+                }),
+                None => scratch
+                    .chunks_mut(band_len)
+                    .enumerate()
+                    .for_each(apply_band),
             }
+
+            #[cfg(not(feature = "multithreaded"))]
+            scratch
+                .chunks_mut(band_len)
+                .enumerate()
+                .for_each(apply_band);
+
+            framebuffer.swap_pixels(&mut scratch);
         }
+
+        self.post_process.scratch = scratch;
+    }
+}
+
+/// Splits the triangles of an instance into chunks, to be projected independently
+fn push_chunks<'a>(chunks: &mut Vec<Chunk<'a>>, mesh: &'a Mesh, instance: &'a MeshInstance) {
+    let count = mesh.triangle_count();
+    for start in (0..count).step_by(PROJECTION_CHUNK) {
+        let end = (start + PROJECTION_CHUNK).min(count);
+        chunks.push((mesh, instance, start..end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::RenderConfig, testing::test_config};
+
+    use super::Renderer;
+
+    #[test]
+    fn zero_tile_size_is_clamped() {
+        let config = RenderConfig {
+            tile_width: 0,
+            ..test_config()
+        };
+        let mut renderer = Renderer::new(16, 12, 90.0, config.clone()).unwrap();
+        assert_eq!(renderer.config().tile_width, 1);
+        renderer.render();
+
+        renderer
+            .set_config(RenderConfig {
+                tile_width: 8,
+                tile_height: 0,
+                ..config
+            })
+            .unwrap();
+        assert_eq!(renderer.config().tile_height, 1);
+        renderer.render();
     }
 }
@@ -0,0 +1,149 @@
+use std::ops::{Index, IndexMut};
+
+use maths::linear::{Vec2f, Vec3f};
+
+use crate::{
+    asset_manager::Named,
+    colour::{RGB, RGBA},
+    config::RenderConfig,
+    texture::Texture,
+};
+
+/// The number of values a vertex shader can pass to a fragment shader
+pub const MAX_VARYINGS: usize = 8;
+
+/// Values written by a vertex shader at each vertex, which are interpolated across the triangle
+/// with perspective correction, and passed to the fragment shader
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Varyings(pub [f32; MAX_VARYINGS]);
+
+impl Varyings {
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self(std::array::from_fn(|i| {
+            self.0[i] + (other.0[i] - self.0[i]) * t
+        }))
+    }
+
+    pub fn scale(&self, scale: f32) -> Self {
+        Self(self.0.map(|value| value * scale))
+    }
+
+    /// Sum of the values at each vertex of a triangle, weighted by barycentric coordinates
+    pub fn weighted_sum(values: &[Varyings; 3], weights: Vec3f) -> Self {
+        Self(std::array::from_fn(|i| {
+            values[0].0[i] * weights.x + values[1].0[i] * weights.y + values[2].0[i] * weights.z
+        }))
+    }
+}
+
+impl Index<usize> for Varyings {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &f32 {
+        &self.0[index]
+    }
+}
+
+impl IndexMut<usize> for Varyings {
+    fn index_mut(&mut self, index: usize) -> &mut f32 {
+        &mut self.0[index]
+    }
+}
+
+/// Values that are the same for every vertex and fragment in a frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Uniforms {
+    /// Seconds since the renderer was created
+    pub time: f32,
+}
+
+/// The attributes of a vertex given to a vertex shader
+#[derive(Clone, Copy, Debug)]
+pub struct VertexInput {
+    /// Position of the vertex in world space, after the instance's transform
+    pub position: Vec3f,
+    /// Normal of the vertex in world space
+    pub normal: Vec3f,
+    pub colour: Vec3f,
+    pub tex_coord: Vec2f,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VertexOutput {
+    /// Position of the vertex in world space, which the shader may move
+    pub position: Vec3f,
+    /// Normal of the vertex in world space, used for lighting
+    pub normal: Vec3f,
+    pub varyings: Varyings,
+}
+
+/// Runs for each vertex of each triangle before it's projected. Instances are culled using their
+/// bounds before any vertices are shaded, so shaders moving vertices should keep them close to
+/// their original positions
+pub trait VertexShader: Send + Sync {
+    fn shade(&self, vertex: &VertexInput, uniforms: &Uniforms) -> VertexOutput;
+}
+
+/// Runs for each pixel covered by a triangle that passes the depth test
+pub trait FragmentShader: Send + Sync {
+    /// Returns the colour of the fragment, which is then blended according to the mesh's alpha
+    /// mode, or `None` to discard it
+    fn shade(&self, fragment: &Fragment, uniforms: &Uniforms) -> Option<RGBA>;
+}
+
+/// A pixel covered by a triangle, with the triangle's attributes interpolated to its centre
+pub struct Fragment<'a> {
+    pub varyings: Varyings,
+    pub colour: RGB,
+    pub tex_coord: Vec2f,
+    /// Position in view space, where the camera is at the origin looking along the z axis
+    pub position: Vec3f,
+    /// Unit length normal in view space
+    pub normal: Vec3f,
+    /// View space depth
+    pub depth: f32,
+
+    pub(crate) texture: Option<&'a Texture>,
+    /// Level of detail of the mesh's texture coordinates at this pixel
+    pub(crate) lod: f32,
+    pub(crate) config: &'a RenderConfig,
+}
+
+impl Fragment<'_> {
+    /// Samples the mesh's texture at the given coordinates, using the level of detail of the mesh's
+    /// own texture coordinates at this pixel. Returns `None` if the mesh has no texture
+    pub fn sample_texture(&self, tex_coord: Vec2f) -> Option<RGBA> {
+        self.texture.map(|texture| unsafe {
+            texture.sample_lod_unchecked(tex_coord.x, tex_coord.y, self.lod, self.config)
+        })
+    }
+}
+
+/// A vertex shader and a fragment shader, which meshes can use in place of the built in shading.
+/// Without a vertex shader, vertices aren't moved and have no varyings. Without a fragment shader,
+/// pixels are shaded according to the mesh's [ShadingMode](crate::ShadingMode)
+pub struct Shader {
+    name: String,
+    pub vertex: Option<Box<dyn VertexShader>>,
+    pub fragment: Option<Box<dyn FragmentShader>>,
+}
+
+impl Named for Shader {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Shader {
+    pub fn new(
+        name: String,
+        vertex: Option<Box<dyn VertexShader>>,
+        fragment: Option<Box<dyn FragmentShader>>,
+    ) -> Self {
+        Self {
+            name,
+            vertex,
+            fragment,
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use maths::linear::{Mat4f, Vec3f};
+
+use crate::{
+    camera::{Camera, Projection, View},
+    config::RenderConfig,
+    framebuffer::Framebuffer,
+    model::transform_point,
+};
+
+/// Depth of the scene as seen from a directional light, looking along the light's direction with an
+/// orthographic projection. Surfaces further from the light than the depth stored for their texel
+/// are in shadow
+pub(crate) struct ShadowMap {
+    framebuffer: Framebuffer,
+    view: View,
+    /// Transforms points from the camera's view space back into world space
+    camera_to_world: Mat4f,
+    bias: f32,
+    pcf: bool,
+}
+
+impl ShadowMap {
+    pub fn new(size: usize) -> Self {
+        let framebuffer = Framebuffer::depth_only(size, size);
+        let view = View {
+            transform: Mat4f::IDENTITY,
+            projection: Projection::Orthographic { scale: 1.0 },
+            half_width: framebuffer.half_width(),
+            half_height: framebuffer.half_height(),
+            near: 0.0,
+            far: 1.0,
+        };
+
+        Self {
+            framebuffer,
+            view,
+            camera_to_world: Mat4f::IDENTITY,
+            bias: 0.0,
+            pcf: false,
+        }
+    }
+
+    /// Width and height of the map, in texels
+    pub fn size(&self) -> usize {
+        self.framebuffer.width()
+    }
+
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Points the light's view along `direction`, covering the configured shadow distance in front
+    /// of the camera, and clears the map ready to draw depth into
+    pub fn update(&mut self, direction: Vec3f, camera: &Camera, config: &RenderConfig) {
+        let distance = config.shadow_distance;
+        let direction = direction.normalise();
+        let up = if direction.y.abs() > 0.99 {
+            Vec3f::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3f::new(0.0, 1.0, 0.0)
+        };
+        let right = up.cross(direction).normalise();
+        let up = direction.cross(right).normalise();
+
+        // the map covers a square twice the shadow distance across, centred half way along it
+        let scale = self.framebuffer.half_width() / distance;
+        let centre = camera.position + camera.direction * (distance * 0.5);
+
+        // Snap the centre to whole texels across the map, so that static shadow edges don't shimmer
+        // as the camera moves
+        let snap = |axis: Vec3f| {
+            let offset = centre.dot(axis) * scale;
+            (offset.round() - offset) / scale
+        };
+        let centre = centre + right * snap(right) + up * snap(up);
+        let position = centre - direction * (2.0 * distance);
+
+        self.view = View {
+            transform: Camera::look_at(&position, &direction, &up),
+            projection: Projection::Orthographic { scale },
+            half_width: self.framebuffer.half_width(),
+            half_height: self.framebuffer.half_height(),
+            near: 0.0,
+            far: 4.0 * distance,
+        };
+        self.camera_to_world = camera.inverse_view_transform();
+        self.bias = config.shadow_bias;
+        self.pcf = config.shadow_pcf;
+
+        self.framebuffer.clear_depth_buffer();
+    }
+
+    /// Converts the depths written by the rasteriser into distances along the light's direction,
+    /// once the map has been drawn
+    pub fn resolve(&mut self) {
+        let view = self.view;
+
+        for depth in self.framebuffer.depth_mut() {
+            if *depth != f32::MAX {
+                *depth = view.linear_depth(*depth);
+            }
+        }
+    }
+
+    /// The fraction of the light reaching a point, given in the camera's view space. With PCF the
+    /// depth test is averaged over the surrounding texels, which softens the edges of shadows
+    pub fn visibility(&self, position: Vec3f) -> f32 {
+        let world = transform_point(position, &self.camera_to_world);
+        let light = transform_point(world, &self.view.transform);
+        // inverse depth is unused by an orthographic projection
+        let texel = self.view.project(light, 0.0);
+        let depth = light.z - self.bias;
+
+        let x = texel.x.floor() as isize;
+        let y = texel.y.floor() as isize;
+
+        if !self.pcf {
+            return self.lit(x, y, depth);
+        }
+
+        let mut lit = 0.0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                lit += self.lit(x + dx, y + dy, depth);
+            }
+        }
+
+        lit / 9.0
+    }
+
+    /// Depth tests a single texel, where everything outside the map is lit
+    #[inline(always)]
+    fn lit(&self, x: isize, y: isize, depth: f32) -> f32 {
+        let size = self.size() as isize;
+        if x < 0 || y < 0 || x >= size || y >= size {
+            return 1.0;
+        }
+
+        let occluder = self.framebuffer.depth()[(y * size + x) as usize];
+        if depth <= occluder {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::{Mat4f, Vec3f};
+
+    use crate::{
+        asset_manager::AssetId,
+        colour::RGB,
+        config::RenderConfig,
+        light::{Light, LightKind},
+        material::Material,
+        model::{AlphaMode, LightingMode, Mesh},
+        renderer::Renderer,
+        shader::{Shader, Uniforms, VertexInput, VertexOutput, VertexShader},
+        shapes::{unit_cube_mesh, unit_quad_mesh},
+        testing::{assert_grey, pixel, render, scene, test_config, white, HEIGHT, WIDTH},
+    };
+
+    /// Renders a single frame of a white wall lit per pixel by a directional light, with a cube
+    /// between the wall and the light. `setup_cube` is called with the cube's mesh before rendering
+    fn render_shadowed_wall(
+        casts_shadows: bool,
+        setup_cube: impl FnOnce(&mut Renderer, AssetId<Mesh>),
+    ) -> Vec<RGB> {
+        let (mut renderer, wall) = scene(
+            test_config(),
+            white(unit_quad_mesh()),
+            Vec3f::new(0.0, 0.0, 4.0),
+        );
+        let cube = renderer.assets_mut().insert_mesh(white(unit_cube_mesh()));
+        renderer
+            .assets_mut()
+            .spawn_mesh_instance(cube, &Mat4f::translate(-1.0, 0.0, 2.5));
+
+        let ambient = Light::new("ambient".into(), LightKind::Ambient, RGB::WHITE, 0.1);
+        let mut sun = Light::new(
+            "sun".into(),
+            LightKind::Directional {
+                direction: Vec3f::new(0.5, 0.0, 1.0),
+            },
+            RGB::WHITE,
+            1.0,
+        );
+        sun.casts_shadows = casts_shadows;
+        renderer.assets_mut().insert_light(ambient);
+        renderer.assets_mut().insert_light(sun);
+
+        let mut material = Material::new("no specular".into());
+        material.specular.colour = RGB::BLACK;
+        let material = renderer.assets_mut().insert_material(material);
+        for mesh in [wall, cube] {
+            renderer
+                .assets_mut()
+                .set_mesh_material(mesh, Some(material));
+            renderer
+                .assets_mut()
+                .set_mesh_lighting(mesh, LightingMode::Pixel);
+        }
+        setup_cube(&mut renderer, cube);
+
+        render(&mut renderer)
+    }
+
+    /// Moves every vertex far above the camera
+    struct LiftVertexShader;
+
+    impl VertexShader for LiftVertexShader {
+        fn shade(&self, vertex: &VertexInput, _uniforms: &Uniforms) -> VertexOutput {
+            VertexOutput {
+                position: vertex.position + Vec3f::new(0.0, 100.0, 0.0),
+                normal: vertex.normal,
+                varyings: Default::default(),
+            }
+        }
+    }
+
+    #[test]
+    fn shadow_mapping() {
+        let lit = 0.1 + 1.0 / 1.25f32.sqrt();
+        // the cube's shadow falls across the middle of the wall, while the top is in the light
+        let shadowed_pixel = |pixels: &[RGB]| pixel(pixels, WIDTH / 2 + 2, HEIGHT / 2);
+        let lit_pixel = |pixels: &[RGB]| pixel(pixels, WIDTH / 2 + 2, HEIGHT / 2 - 6);
+
+        let pixels = render_shadowed_wall(true, |_, _| {});
+        assert_grey(shadowed_pixel(&pixels), 0.1);
+        assert_grey(lit_pixel(&pixels), lit);
+
+        let pixels = render_shadowed_wall(false, |_, _| {});
+        assert_grey(shadowed_pixel(&pixels), lit);
+        assert_grey(lit_pixel(&pixels), lit);
+
+        // with room for only one light, the sun casting shadows is kept over the ambient light
+        let pixels = render_shadowed_wall(true, |renderer, _| {
+            let config = RenderConfig {
+                max_lights: 1,
+                ..test_config()
+            };
+            renderer.set_config(config).unwrap();
+        });
+        assert_grey(shadowed_pixel(&pixels), 0.0);
+        assert_grey(lit_pixel(&pixels), lit - 0.1);
+
+        // transparency is ignored when drawing the shadow map, so blended meshes cast solid shadows
+        let pixels = render_shadowed_wall(true, |renderer, cube| {
+            renderer
+                .assets_mut()
+                .set_mesh_alpha_mode(cube, AlphaMode::Blend);
+        });
+        assert_grey(shadowed_pixel(&pixels), 0.1);
+        assert_grey(lit_pixel(&pixels), lit);
+
+        // vertex shaders also run when drawing the shadow map, so the cube moved out of view no
+        // longer shadows the wall
+        let pixels = render_shadowed_wall(true, |renderer, cube| {
+            let shader = Shader::new("lift".into(), Some(Box::new(LiftVertexShader)), None);
+            let shader = renderer.assets_mut().insert_shader(shader);
+            renderer.assets_mut().set_mesh_shader(cube, Some(shader));
+        });
+        assert_grey(shadowed_pixel(&pixels), lit);
+    }
+}
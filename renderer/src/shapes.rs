@@ -1,158 +1,215 @@
-use maths::linear::{Vec2f, Vec3f};
-
-use crate::model::{Mesh, Vertex};
+use maths::linear::{Vec2f, Vec3f, Vec4f};
 
+use crate::model::{generate_tangents, Mesh, Vertex};
 
 pub fn unit_quad_mesh() -> Mesh {
-    let vertices = vec![
+    let mut vertices = vec![
         Vertex {
             position: Vec3f::new(1.0, -1.0, 0.0),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(1.0, -1.0, 0.0),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-1.0, -1.0, 0.0),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(-1.0, -1.0, 0.0),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-1.0, 1.0, 0.0),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(-1.0, 1.0, 0.0),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(1.0, 1.0, 0.0),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(1.0, 1.0, 0.0),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
     ];
 
     let indices = vec![0, 1, 2, 2, 3, 0];
 
-    Mesh::new(String::from("Quad"), vertices, indices, None)
+    generate_tangents(&mut vertices, &indices);
+
+    Mesh::new(String::from("Quad"), vertices, indices)
 }
 
 pub fn unit_cube_mesh() -> Mesh {
-    let vertices = vec![
+    let mut vertices = vec![
         Vertex {
             position: Vec3f::new(0.5, -0.5, -0.5),
+            normal: Vec3f::new(0.0, -1.0, 0.0),
             colour: Vec3f::new(0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, -0.5),
+            normal: Vec3f::new(0.0, -1.0, 0.0),
             colour: Vec3f::new(-0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, 0.5),
+            normal: Vec3f::new(0.0, -1.0, 0.0),
             colour: Vec3f::new(-0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, -0.5, 0.5),
+            normal: Vec3f::new(0.0, -1.0, 0.0),
             colour: Vec3f::new(0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, -0.5),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(-0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, -0.5, -0.5),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, -0.5),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, -0.5),
+            normal: Vec3f::new(0.0, 0.0, -1.0),
             colour: Vec3f::new(-0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, -0.5),
+            normal: Vec3f::new(0.0, 1.0, 0.0),
             colour: Vec3f::new(-0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, -0.5),
+            normal: Vec3f::new(0.0, 1.0, 0.0),
             colour: Vec3f::new(0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, 0.5),
+            normal: Vec3f::new(0.0, 1.0, 0.0),
             colour: Vec3f::new(0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, 0.5),
+            normal: Vec3f::new(0.0, 1.0, 0.0),
             colour: Vec3f::new(-0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, -0.5, 0.5),
+            normal: Vec3f::new(0.0, 0.0, 1.0),
             colour: Vec3f::new(0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, 0.5),
+            normal: Vec3f::new(0.0, 0.0, 1.0),
             colour: Vec3f::new(-0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, 0.5),
+            normal: Vec3f::new(0.0, 0.0, 1.0),
             colour: Vec3f::new(-0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, 0.5),
+            normal: Vec3f::new(0.0, 0.0, 1.0),
             colour: Vec3f::new(0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, -0.5, -0.5),
+            normal: Vec3f::new(1.0, 0.0, 0.0),
             colour: Vec3f::new(0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, -0.5, 0.5),
+            normal: Vec3f::new(1.0, 0.0, 0.0),
             colour: Vec3f::new(0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, 0.5),
+            normal: Vec3f::new(1.0, 0.0, 0.0),
             colour: Vec3f::new(0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(0.5, 0.5, -0.5),
+            normal: Vec3f::new(1.0, 0.0, 0.0),
             colour: Vec3f::new(0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, 0.5),
+            normal: Vec3f::new(-1.0, 0.0, 0.0),
             colour: Vec3f::new(-0.5, -0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, -0.5, -0.5),
+            normal: Vec3f::new(-1.0, 0.0, 0.0),
             colour: Vec3f::new(-0.5, -0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 0.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, -0.5),
+            normal: Vec3f::new(-1.0, 0.0, 0.0),
             colour: Vec3f::new(-0.5, 0.5, -0.5),
             tex_coord: Vec2f::new(1.0, 1.0),
+            tangent: Vec4f::default(),
         },
         Vertex {
             position: Vec3f::new(-0.5, 0.5, 0.5),
+            normal: Vec3f::new(-1.0, 0.0, 0.0),
             colour: Vec3f::new(-0.5, 0.5, 0.5),
             tex_coord: Vec2f::new(0.0, 1.0),
+            tangent: Vec4f::default(),
         },
     ];
 
@@ -161,7 +218,9 @@ pub fn unit_cube_mesh() -> Mesh {
         17, 18, 16, 19, 20, 22, 21, 22, 20, 23,
     ];
 
-    Mesh::new(String::from("Cube"), vertices, indices, None)
+    generate_tangents(&mut vertices, &indices);
+
+    Mesh::new(String::from("Cube"), vertices, indices)
 }
 
 pub fn unit_sphere_mesh(resolution: usize) -> Mesh {
@@ -301,12 +360,16 @@ pub fn unit_sphere_mesh(resolution: usize) -> Mesh {
 
                 vertices.push(Vertex {
                     position: n,
+                    normal: n.normalise(),
                     colour: n,
                     tex_coord: Vec2f::new(u as f32, v as f32) / resolution as f32,
+                    tangent: Vec4f::default(),
                 });
             }
         }
     }
 
-    Mesh::new(String::from("Sphere"), vertices, indices, None)
+    generate_tangents(&mut vertices, &indices);
+
+    Mesh::new(String::from("Sphere"), vertices, indices)
 }
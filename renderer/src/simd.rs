@@ -0,0 +1,113 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Four `f32` lanes, for processing several pixels at once, held in an SSE register. SSE2 is always
+/// available on x86_64
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct F32x4(__m128);
+
+/// Four `f32` lanes, for processing several pixels at once. Without SSE, these are held in a plain
+/// array, which the compiler is usually able to vectorise itself
+#[cfg(not(target_arch = "x86_64"))]
+#[derive(Clone, Copy, Debug)]
+#[repr(transparent)]
+pub struct F32x4([f32; 4]);
+
+impl F32x4 {
+    pub const LANES: usize = 4;
+
+    /// Loads up to four values from the slice, with any missing lanes set to zero
+    #[inline(always)]
+    pub fn load_partial(values: &[f32]) -> Self {
+        if values.len() >= Self::LANES {
+            Self::new(values[0], values[1], values[2], values[3])
+        } else {
+            let mut lanes = [0.0; Self::LANES];
+            lanes[..values.len()].copy_from_slice(values);
+            Self::new(lanes[0], lanes[1], lanes[2], lanes[3])
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl F32x4 {
+    #[inline(always)]
+    pub fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+        unsafe { Self(_mm_setr_ps(a, b, c, d)) }
+    }
+
+    #[inline(always)]
+    pub fn splat(x: f32) -> Self {
+        unsafe { Self(_mm_set1_ps(x)) }
+    }
+
+    #[inline(always)]
+    pub fn to_array(self) -> [f32; 4] {
+        let mut lanes = [0.0; Self::LANES];
+        unsafe { _mm_storeu_ps(lanes.as_mut_ptr(), self.0) };
+        lanes
+    }
+
+    /// Returns a mask with a bit set for each lane where `self` is less than `other`
+    #[inline(always)]
+    pub fn lt_mask(self, other: Self) -> u32 {
+        unsafe { _mm_movemask_ps(_mm_cmplt_ps(self.0, other.0)) as u32 }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+impl F32x4 {
+    #[inline(always)]
+    pub fn new(a: f32, b: f32, c: f32, d: f32) -> Self {
+        Self([a, b, c, d])
+    }
+
+    #[inline(always)]
+    pub fn splat(x: f32) -> Self {
+        Self([x; 4])
+    }
+
+    #[inline(always)]
+    pub fn to_array(self) -> [f32; 4] {
+        self.0
+    }
+
+    /// Returns a mask with a bit set for each lane where `self` is less than `other`
+    #[inline(always)]
+    pub fn lt_mask(self, other: Self) -> u32 {
+        let mut mask = 0;
+        for lane in 0..Self::LANES {
+            mask |= ((self.0[lane] < other.0[lane]) as u32) << lane;
+        }
+        mask
+    }
+}
+
+macro_rules! impl_op {
+    ($trait:ident, $fn:ident, $intrinsic:ident, $op:tt) => {
+        impl $trait for F32x4 {
+            type Output = Self;
+
+            #[cfg(target_arch = "x86_64")]
+            #[inline(always)]
+            fn $fn(self, rhs: Self) -> Self {
+                unsafe { Self($intrinsic(self.0, rhs.0)) }
+            }
+
+            #[cfg(not(target_arch = "x86_64"))]
+            #[inline(always)]
+            fn $fn(self, rhs: Self) -> Self {
+                Self(std::array::from_fn(|lane| self.0[lane] $op rhs.0[lane]))
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, _mm_add_ps, +);
+impl_op!(Sub, sub, _mm_sub_ps, -);
+impl_op!(Mul, mul, _mm_mul_ps, *);
+impl_op!(Div, div, _mm_div_ps, /);
@@ -0,0 +1,43 @@
+use maths::linear::{Mat4f, Vec2f};
+
+use crate::{
+    camera::View, colour::RGB, config::RenderConfig, framebuffer::Framebuffer,
+    model::transform_direction, texture::Cubemap,
+};
+
+/// Fills every sample that nothing has been drawn to with the cubemap, looking along the camera's
+/// ray through the centre of the sample's pixel. The sky is infinitely far away, so it's never
+/// clipped by the far plane
+pub(crate) fn draw_skybox(
+    framebuffer: &mut Framebuffer,
+    cubemap: &Cubemap,
+    view: &View,
+    camera_to_world: &Mat4f,
+    config: &RenderConfig,
+) {
+    let width = framebuffer.width();
+    let lod = cubemap.lod(view.pixel_angle());
+    let samples = framebuffer.samples();
+    let (colours, depth) = framebuffer.samples_mut();
+
+    for (index, (colours, depth)) in colours
+        .chunks_exact_mut(samples)
+        .zip(depth.chunks_exact(samples))
+        .enumerate()
+    {
+        if depth.iter().all(|&depth| depth != f32::MAX) {
+            continue;
+        }
+
+        let x = (index % width) as f32 + 0.5;
+        let y = (index / width) as f32 + 0.5;
+        let direction = transform_direction(view.ray(Vec2f::new(x, y)), camera_to_world);
+        let sky = RGB::from(cubemap.sample(direction, lod, config));
+
+        for (colour, &depth) in colours.iter_mut().zip(depth) {
+            if depth == f32::MAX {
+                *colour = sky;
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Helpers shared by tests which render whole frames
+
+use maths::linear::{Mat4f, Vec3f};
+
+use crate::{
+    asset_manager::AssetId, colour::RGB, config::RenderConfig, model::Mesh, renderer::Renderer,
+};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 48;
+pub const CLEAR_COLOUR: RGB = RGB::BLACK;
+
+/// Renders with one thread, unless a test compares against threaded rendering, so that tests can
+/// run under Miri with `cargo miri test --no-default-features`
+pub fn test_config() -> RenderConfig {
+    RenderConfig {
+        threads: 1,
+        res_scale: 1.0,
+        ..Default::default()
+    }
+}
+
+/// A renderer for a [WIDTH] by [HEIGHT] screen, cleared to [CLEAR_COLOUR], with `mesh` spawned at
+/// `position`. The camera sits at the origin, looking along +z
+pub fn scene(config: RenderConfig, mesh: Mesh, position: Vec3f) -> (Renderer, AssetId<Mesh>) {
+    let mut renderer = Renderer::new(WIDTH, HEIGHT, 90.0, config).unwrap();
+    renderer.set_clear_colour(CLEAR_COLOUR);
+
+    let mesh = renderer.assets_mut().insert_mesh(mesh);
+    renderer
+        .assets_mut()
+        .spawn_mesh_instance(mesh, &Mat4f::translate(position.x, position.y, position.z));
+
+    (renderer, mesh)
+}
+
+/// Renders a frame, returning its pixels
+pub fn render(renderer: &mut Renderer) -> Vec<RGB> {
+    renderer.render();
+    renderer.pixels().to_vec()
+}
+
+/// Sets every vertex colour to white
+pub fn white(mut mesh: Mesh) -> Mesh {
+    for vertex in mesh.vertices.iter_mut() {
+        vertex.colour = Vec3f::uniform(1.0);
+    }
+    mesh
+}
+
+pub fn pixel(pixels: &[RGB], x: usize, y: usize) -> RGB {
+    pixels[y * WIDTH + x]
+}
+
+pub fn centre(pixels: &[RGB]) -> RGB {
+    pixel(pixels, WIDTH / 2, HEIGHT / 2)
+}
+
+/// Asserts that the summed error across the channels is less than `tolerance`
+pub fn assert_near(colour: RGB, expected: RGB, tolerance: f32) {
+    let error = (colour.r - expected.r).abs()
+        + (colour.g - expected.g).abs()
+        + (colour.b - expected.b).abs();
+    assert!(error < tolerance, "expected {expected:?}, got {colour:?}");
+}
+
+pub fn assert_grey(colour: RGB, expected: f32) {
+    let channels = [colour.r, colour.g, colour.b];
+    assert!(
+        channels.iter().all(|c| (c - expected).abs() < 1e-4),
+        "expected {expected}, got {colour:?}"
+    );
+}
@@ -1,16 +1,16 @@
 use std::{fs::File, path::Path};
 
-use crate::colour::RGB;
+use crate::colour::RGBA;
 
 #[derive(Debug)]
 pub struct Bitmap {
     width: usize,
     height: usize,
-    pixels: Vec<RGB>,
+    pixels: Vec<RGBA>,
 }
 
 impl Bitmap {
-    pub fn new(width: usize, height: usize, pixels: Vec<RGB>) -> Self {
+    pub fn new(width: usize, height: usize, pixels: Vec<RGBA>) -> Self {
         Self {
             width,
             height,
@@ -31,28 +31,33 @@ impl Bitmap {
 
         let info = reader.next_frame(&mut buffer)?;
 
-        assert_eq!(
-            info.bit_depth,
-            png::BitDepth::Eight,
-            "Unsupported bit depth"
-        );
-
-        assert_eq!(
-            info.color_type,
-            png::ColorType::Rgba,
-            "Unsupported colour type"
-        );
+        if info.bit_depth != png::BitDepth::Eight {
+            anyhow::bail!("Unsupported bit depth {:?}", info.bit_depth);
+        }
 
-        let pixels = buffer
-            .chunks_exact(4)
-            .map(|bytes| {
-                let r = bytes[0];
-                let g = bytes[1];
-                let b = bytes[2];
-                // let a = bytes[3];
-                RGB::from_u8(r, g, b)
-            })
-            .collect();
+        // grayscale and RGB images are widened to RGBA, with opaque alpha if they have none
+        let bytes = &buffer[..info.buffer_size()];
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => bytes
+                .chunks_exact(4)
+                .map(|bytes| RGBA::from_u8(bytes[0], bytes[1], bytes[2], bytes[3]))
+                .collect(),
+            png::ColorType::Rgb => bytes
+                .chunks_exact(3)
+                .map(|bytes| RGBA::from_u8(bytes[0], bytes[1], bytes[2], u8::MAX))
+                .collect(),
+            png::ColorType::GrayscaleAlpha => bytes
+                .chunks_exact(2)
+                .map(|bytes| RGBA::from_u8(bytes[0], bytes[0], bytes[0], bytes[1]))
+                .collect(),
+            png::ColorType::Grayscale => bytes
+                .iter()
+                .map(|&grey| RGBA::from_u8(grey, grey, grey, u8::MAX))
+                .collect(),
+            png::ColorType::Indexed => {
+                anyhow::bail!("Unsupported colour type {:?}", info.color_type)
+            }
+        };
 
         Ok(Self::new(info.width as usize, info.height as usize, pixels))
     }
@@ -65,7 +70,54 @@ impl Bitmap {
         self.height
     }
 
-    pub fn pixels(&self) -> &[RGB] {
+    pub fn pixels(&self) -> &[RGBA] {
         &self.pixels
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::BufWriter};
+
+    use crate::colour::RGBA;
+
+    use super::Bitmap;
+
+    /// Writes a 2x1 image with the given colour type, then loads it back
+    fn load_png(name: &str, colour_type: png::ColorType, bytes: &[u8]) -> Bitmap {
+        let path = std::env::temp_dir().join(format!("software-3d-{name}.png"));
+        {
+            let file = BufWriter::new(File::create(&path).unwrap());
+            let mut encoder = png::Encoder::new(file, 2, 1);
+            encoder.set_color(colour_type);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(bytes).unwrap();
+        }
+
+        let bitmap = Bitmap::from_path_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bitmap
+    }
+
+    #[test]
+    fn colour_types_are_converted_to_rgba() {
+        let expected = [
+            RGBA::from_u8(0, 0, 0, 255),
+            RGBA::from_u8(255, 255, 255, 255),
+        ];
+
+        let grayscale = load_png("grayscale", png::ColorType::Grayscale, &[0, 255]);
+        assert_eq!(grayscale.pixels(), expected);
+
+        let grayscale_alpha = load_png(
+            "grayscale-alpha",
+            png::ColorType::GrayscaleAlpha,
+            &[0, 255, 255, 255],
+        );
+        assert_eq!(grayscale_alpha.pixels(), expected);
+
+        let rgb = load_png("rgb", png::ColorType::Rgb, &[0, 0, 0, 255, 255, 255]);
+        assert_eq!(rgb.pixels(), expected);
+    }
+}
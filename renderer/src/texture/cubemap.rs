@@ -0,0 +1,275 @@
+use std::{
+    f32::consts::{FRAC_2_PI, PI},
+    path::Path,
+};
+
+use maths::linear::Vec3f;
+
+use crate::{asset_manager::Named, colour::RGBA, config::RenderConfig, util::file_name};
+
+use super::{
+    bitmap::Bitmap,
+    texture::{AddressMode, FilterMode, Texture},
+};
+
+/// Direction each face looks along from the centre of the cube, followed by the directions of
+/// increasing x and decreasing y across its image. Faces are stored in the order +x, -x, +y, -y,
+/// +z, -z, and each is seen from inside the cube, with +y up and +z forward
+const FACES: [[Vec3f; 3]; 6] = [
+    [
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 0.0, -1.0),
+        vec3(0.0, 1.0, 0.0),
+    ],
+    [
+        vec3(-1.0, 0.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+        vec3(0.0, 1.0, 0.0),
+    ],
+    [
+        vec3(0.0, 1.0, 0.0),
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 0.0, -1.0),
+    ],
+    [
+        vec3(0.0, -1.0, 0.0),
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+    ],
+    [
+        vec3(0.0, 0.0, 1.0),
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+    ],
+    [
+        vec3(0.0, 0.0, -1.0),
+        vec3(-1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+    ],
+];
+
+const fn vec3(x: f32, y: f32, z: f32) -> Vec3f {
+    Vec3f { x, y, z }
+}
+
+/// Six square textures covering the inside of a cube, which are sampled by direction rather than
+/// by texture coordinates. Used to draw skyboxes and reflections
+#[derive(Debug)]
+pub struct Cubemap {
+    name: String,
+    faces: [Texture; 6],
+}
+
+impl Named for Cubemap {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Cubemap {
+    /// Loads a cubemap from an image per face, in the order +x, -x, +y, -y, +z, -z. The images
+    /// must be square and all the same size. The cubemap is named after the first image
+    pub fn from_paths_png<P: AsRef<Path>>(paths: [P; 6]) -> Result<Self, anyhow::Error> {
+        let name = file_name(paths[0].as_ref()).unwrap();
+        let mut faces = Vec::with_capacity(6);
+        for path in paths.iter() {
+            faces.push(Texture::from_path_png(path)?);
+        }
+
+        let size = faces[0].width();
+        for (face, path) in faces.iter().zip(paths.iter()) {
+            if face.width() != size || face.height() != size {
+                anyhow::bail!(
+                    "Cubemap face {:?} is {}x{}, expected {size}x{size}",
+                    path.as_ref(),
+                    face.width(),
+                    face.height()
+                );
+            }
+        }
+
+        Ok(Self::new(name, faces.try_into().unwrap()))
+    }
+
+    /// Loads a cubemap from a single equirectangular image, which spans 360 degrees horizontally
+    /// and 180 degrees vertically, with the centre of the image facing +z. Each face is a quarter
+    /// of the width of the image
+    pub fn from_path_png_equirectangular(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let bitmap = Bitmap::from_path_png(path.as_ref())?;
+        let name = file_name(path.as_ref()).unwrap();
+        let size = (bitmap.width() / 4).max(1);
+
+        let faces = FACES.map(|[forward, right, up]| {
+            let mut pixels = Vec::with_capacity(size * size);
+            for y in 0..size {
+                for x in 0..size {
+                    // the direction through the centre of the texel
+                    let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                    let t = 1.0 - (y as f32 + 0.5) / size as f32 * 2.0;
+                    let direction = (forward + right * s + up * t).normalise();
+
+                    pixels.push(sample_equirectangular(&bitmap, direction));
+                }
+            }
+
+            Texture::from_pixels(name.clone(), size, size, pixels)
+        });
+
+        Ok(Self::new(name, faces))
+    }
+
+    /// Creates a cubemap from square textures of the same size, in the order +x, -x, +y, -y, +z,
+    /// -z. Each face is clamped to its edges, so that samples don't wrap around to the opposite
+    /// side of the face, and smoothed with bilinear filtering
+    pub fn new(name: String, mut faces: [Texture; 6]) -> Self {
+        for face in faces.iter_mut() {
+            face.filter = FilterMode::Bilinear;
+            face.set_address_modes(
+                AddressMode::ClampToEdge,
+                AddressMode::ClampToEdge,
+                RGBA::BLACK,
+            );
+        }
+
+        Self { name, faces }
+    }
+
+    /// Width and height of each face, in texels
+    pub fn size(&self) -> usize {
+        self.faces[0].width()
+    }
+
+    pub fn faces(&self) -> &[Texture; 6] {
+        &self.faces
+    }
+
+    pub fn faces_mut(&mut self) -> &mut [Texture; 6] {
+        &mut self.faces
+    }
+
+    /// The level of detail for samples spread `angle` radians apart, such that level 0 is used when
+    /// a texel covers roughly that angle
+    pub fn lod(&self, angle: f32) -> f32 {
+        // each face covers a quarter turn
+        (angle * self.size() as f32 * FRAC_2_PI).log2() + self.faces[0].lod_bias
+    }
+
+    /// Samples the face that a direction points at, at the given level of detail. The direction
+    /// doesn't need to be normalised, but must not be zero
+    pub fn sample(&self, direction: Vec3f, lod: f32, config: &RenderConfig) -> RGBA {
+        let abs = Vec3f::new(direction.x.abs(), direction.y.abs(), direction.z.abs());
+        let face = if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x > 0.0 {
+                0
+            } else {
+                1
+            }
+        } else if abs.y >= abs.z {
+            if direction.y > 0.0 {
+                2
+            } else {
+                3
+            }
+        } else if direction.z > 0.0 {
+            4
+        } else {
+            5
+        };
+
+        // project onto the face, where each axis spans -1.0 to 1.0
+        let [forward, right, up] = FACES[face];
+        let scale = 1.0 / direction.dot(forward);
+        let x = (direction.dot(right) * scale + 1.0) * 0.5;
+        let y = (1.0 - direction.dot(up) * scale) * 0.5;
+
+        // the coordinates are clamped by the faces' address modes
+        unsafe { self.faces[face].sample_lod_unchecked(x, y, lod, config) }
+    }
+}
+
+/// Bilinearly samples an equirectangular image in a direction, wrapping around horizontally
+fn sample_equirectangular(bitmap: &Bitmap, direction: Vec3f) -> RGBA {
+    let width = bitmap.width();
+    let height = bitmap.height();
+
+    // longitude is zero along +z, increasing towards +x, and latitude is zero at the horizon
+    let longitude = direction.x.atan2(direction.z);
+    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+    let x = (longitude / (2.0 * PI) + 0.5) * width as f32 - 0.5;
+    let y = (0.5 - latitude / PI) * height as f32 - 0.5;
+
+    let x_floor = x.floor();
+    let y_floor = y.floor();
+    let fract_x = x - x_floor;
+    let fract_y = y - y_floor;
+
+    let wrap_x = |x: isize| x.rem_euclid(width as isize) as usize;
+    let clamp_y = |y: isize| y.clamp(0, height as isize - 1) as usize;
+    let (x0, x1) = (wrap_x(x_floor as isize), wrap_x(x_floor as isize + 1));
+    let (y0, y1) = (clamp_y(y_floor as isize), clamp_y(y_floor as isize + 1));
+    let texel = |x: usize, y: usize| bitmap.pixels()[y * width + x];
+
+    let top = texel(x1, y0).blend(texel(x0, y0), fract_x);
+    let bottom = texel(x1, y1).blend(texel(x0, y1), fract_x);
+    bottom.blend(top, fract_y)
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::Vec3f;
+
+    use crate::{
+        colour::{RGB, RGBA},
+        model::ShadingMode,
+        shapes::unit_cube_mesh,
+        testing::{centre, pixel, render, scene, test_config},
+        texture::Texture,
+    };
+
+    use super::Cubemap;
+
+    #[test]
+    fn skybox_fills_background() {
+        let face_colours = [
+            RGB::RED,
+            RGB::GREEN,
+            RGB::BLUE,
+            RGB::WHITE,
+            RGB::new(0.5, 0.5, 0.5),
+            RGB::new(0.25, 0.0, 0.5),
+        ];
+        let render_towards = |direction: Vec3f| {
+            // the cube is only ever in front of the camera when it looks along +z
+            let (mut renderer, cube) =
+                scene(test_config(), unit_cube_mesh(), Vec3f::new(0.0, 0.0, 3.0));
+            renderer
+                .assets_mut()
+                .set_mesh_shading(cube, ShadingMode::Flat(RGB::BLACK));
+
+            let faces = face_colours
+                .map(|colour| Texture::from_pixels("face".into(), 1, 1, vec![RGBA::from(colour)]));
+            let cubemap = renderer
+                .assets_mut()
+                .insert_cubemap(Cubemap::new("sky".into(), faces));
+            renderer.set_skybox(Some(cubemap));
+
+            let camera = renderer.camera_mut();
+            camera.direction = direction;
+            camera.update_view();
+
+            render(&mut renderer)
+        };
+
+        let pixels = render_towards(Vec3f::new(0.0, 0.0, 1.0));
+        assert_eq!(centre(&pixels), RGB::BLACK);
+        assert_eq!(pixel(&pixels, 0, 0), face_colours[4]);
+
+        for (direction, colour) in [
+            (Vec3f::new(1.0, 0.0, 0.0), face_colours[0]),
+            (Vec3f::new(-1.0, 0.0, 0.0), face_colours[1]),
+            (Vec3f::new(0.0, 0.0, -1.0), face_colours[5]),
+        ] {
+            assert_eq!(centre(&render_towards(direction)), colour);
+        }
+    }
+}
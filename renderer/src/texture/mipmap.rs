@@ -1,6 +1,6 @@
-use crate::{colour::RGB, MIP_LEVELS};
+use crate::colour::RGBA;
 
-use super::bitmap::Bitmap;
+use super::{bitmap::Bitmap, texture::AddressMode};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct MipLevel {
@@ -11,31 +11,42 @@ pub struct MipLevel {
     pub offset: usize,
 }
 
-pub fn calculate_mip_levels(bitmap: &Bitmap) -> [MipLevel; MIP_LEVELS] {
+/// Calculates the full mip chain for the given bitmap, halving the dimensions of each subsequent
+/// level until either axis would reach zero. The number of levels actually sampled from is capped
+/// at runtime by [RenderConfig::mip_levels](crate::RenderConfig::mip_levels)
+pub fn calculate_mip_levels(bitmap: &Bitmap) -> Vec<MipLevel> {
     let mut mip_width = bitmap.width();
     let mut mip_height = bitmap.height();
     let mut offset = 0;
+    let mut levels = Vec::new();
 
-    core::array::from_fn(|_| {
-        let level = MipLevel {
+    while mip_width > 0 && mip_height > 0 {
+        levels.push(MipLevel {
             width: mip_width,
             height: mip_height,
             width_f: mip_width as f32,
             height_f: mip_height as f32,
             offset,
-        };
+        });
 
         offset += mip_width * mip_height;
         mip_width /= 2;
         mip_height /= 2;
+    }
 
-        level
-    })
+    levels
 }
 
-/// Generates mip maps for the given texture, assuming that the first level is already filled
-pub fn generate_mip_maps(levels: &[MipLevel], buffer: &mut [RGB]) {
-    for i in 1..MIP_LEVELS {
+/// Generates mip maps for the given texture, assuming that the first level is already filled. Texels
+/// beyond the edges of each level are sampled according to the address modes
+pub fn generate_mip_maps(
+    levels: &[MipLevel],
+    buffer: &mut [RGBA],
+    address_u: AddressMode,
+    address_v: AddressMode,
+    border_colour: RGBA,
+) {
+    for i in 1..levels.len() {
         let src_width = levels[i - 1].width;
         let src_height = levels[i - 1].height;
         let read_index = levels[i - 1].offset;
@@ -45,27 +56,45 @@ pub fn generate_mip_maps(levels: &[MipLevel], buffer: &mut [RGB]) {
         let (src, dst) = buffer.split_at_mut(write_index);
         let src = &src[read_index..];
 
-        downscale_3x3_box_filter(src, src_width, src_height, dst);
+        let sampler = EdgeSampler {
+            src,
+            src_width,
+            src_height,
+            address_u,
+            address_v,
+            border_colour,
+        };
+
+        downscale_3x3_box_filter(&sampler, dst);
     }
 }
 
-pub fn sample_clamp(src: &[RGB], src_width: usize, src_height: usize, x: isize, y: isize) -> RGB {
-    let x = x.clamp(0, src_width as isize - 1) as usize;
-    let y = y.clamp(0, src_height as isize - 1) as usize;
-
-    src[y * src_width + x]
+/// Samples texels from a single mip level, resolving coordinates beyond the edges using the
+/// texture's address modes
+pub struct EdgeSampler<'a> {
+    pub src: &'a [RGBA],
+    pub src_width: usize,
+    pub src_height: usize,
+    pub address_u: AddressMode,
+    pub address_v: AddressMode,
+    pub border_colour: RGBA,
 }
 
-pub fn sample_wrap(src: &[RGB], src_width: usize, src_height: usize, x: isize, y: isize) -> RGB {
-    let x = x.rem_euclid(src_width as isize) as usize;
-    let y = y.rem_euclid(src_height as isize) as usize;
+impl EdgeSampler<'_> {
+    pub fn sample(&self, x: isize, y: isize) -> RGBA {
+        let x = self.address_u.resolve(x, self.src_width);
+        let y = self.address_v.resolve(y, self.src_height);
 
-    src[y * src_width + x]
+        match (x, y) {
+            (Some(x), Some(y)) => self.src[y * self.src_width + x],
+            _ => self.border_colour,
+        }
+    }
 }
 
-pub fn downscale_3x3_box_filter(src: &[RGB], src_width: usize, src_height: usize, dst: &mut [RGB]) {
-    let dst_width = src_width / 2;
-    let dst_height = src_height / 2;
+pub fn downscale_3x3_box_filter(sampler: &EdgeSampler, dst: &mut [RGBA]) {
+    let dst_width = sampler.src_width / 2;
+    let dst_height = sampler.src_height / 2;
     assert!(dst.len() >= dst_width * dst_height);
 
     for dst_y in 0..dst_height {
@@ -78,32 +107,35 @@ pub fn downscale_3x3_box_filter(src: &[RGB], src_width: usize, src_height: usize
             //  g, h, i]
 
             let samples = [
-                sample_wrap(src, src_width, src_height, src_x - 1, src_y - 1),
-                sample_wrap(src, src_width, src_height, src_x, src_y - 1),
-                sample_wrap(src, src_width, src_height, src_x + 1, src_y - 1),
-                sample_wrap(src, src_width, src_height, src_x - 1, src_y),
-                sample_wrap(src, src_width, src_height, src_x, src_y),
-                sample_wrap(src, src_width, src_height, src_x + 1, src_y),
-                sample_wrap(src, src_width, src_height, src_x - 1, src_y + 1),
-                sample_wrap(src, src_width, src_height, src_x, src_y + 1),
-                sample_wrap(src, src_width, src_height, src_x + 1, src_y + 1),
+                sampler.sample(src_x - 1, src_y - 1),
+                sampler.sample(src_x, src_y - 1),
+                sampler.sample(src_x + 1, src_y - 1),
+                sampler.sample(src_x - 1, src_y),
+                sampler.sample(src_x, src_y),
+                sampler.sample(src_x + 1, src_y),
+                sampler.sample(src_x - 1, src_y + 1),
+                sampler.sample(src_x, src_y + 1),
+                sampler.sample(src_x + 1, src_y + 1),
             ];
 
             let mut r = 0.0;
             let mut g = 0.0;
             let mut b = 0.0;
+            let mut a = 0.0;
 
             for sample in samples {
                 r += sample.r;
                 g += sample.g;
                 b += sample.b;
+                a += sample.a;
             }
 
             r /= 9.0;
             g /= 9.0;
             b /= 9.0;
+            a /= 9.0;
 
-            dst[dst_y * dst_width + dst_x] = RGB::new(r, g, b);
+            dst[dst_y * dst_width + dst_x] = RGBA::new(r, g, b, a);
         }
     }
 }
@@ -1,7 +1,9 @@
-mod texture;
 mod bitmap;
+mod cubemap;
 mod mipmap;
+mod texture;
 
-pub use texture::*;
+pub(crate) use bitmap::Bitmap;
+pub use cubemap::Cubemap;
 pub use mipmap::MipLevel;
-
+pub use texture::*;
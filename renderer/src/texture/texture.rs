@@ -1,17 +1,107 @@
 use std::path::Path;
 
-use crate::{asset_manager::Named, colour::RGB, util::file_name, DIM_POW_2, MIP_LEVELS};
+use maths::linear::Vec2f;
+
+use crate::{asset_manager::Named, colour::RGBA, config::RenderConfig, util::file_name};
 
 use super::{
     bitmap::Bitmap,
     mipmap::{calculate_mip_levels, generate_mip_maps, MipLevel},
 };
 
+/// Determines how texels are combined when sampling a texture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilterMode {
+    /// The single nearest texel, from the nearest mip level
+    #[default]
+    Nearest,
+    /// A weighted average of the four nearest texels, from the nearest mip level
+    Bilinear,
+    /// Bilinear samples from the two nearest mip levels, blended by the fractional level of detail
+    Trilinear,
+}
+
+/// Determines how texture coordinates outside of the range 0.0 to 1.0 are handled, per axis
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressMode {
+    /// The texture is tiled
+    #[default]
+    Repeat,
+    /// The texture is tiled, flipping every other repetition
+    MirroredRepeat,
+    /// Coordinates are clamped to the edge texels
+    ClampToEdge,
+    /// Coordinates outside of the texture take the texture's border colour
+    ClampToBorder,
+}
+
+impl AddressMode {
+    /// Maps a texture coordinate into the range 0.0 to 1.0. Coordinates are left unchanged for
+    /// [AddressMode::ClampToBorder], so that the border can be detected per texel instead
+    #[inline(always)]
+    fn normalise(self, x: f32) -> f32 {
+        match self {
+            AddressMode::Repeat => x - x.floor(),
+            AddressMode::MirroredRepeat => {
+                let x = x - (x * 0.5).floor() * 2.0;
+                if x > 1.0 {
+                    2.0 - x
+                } else {
+                    x
+                }
+            }
+            AddressMode::ClampToEdge => x.clamp(0.0, 1.0),
+            AddressMode::ClampToBorder => x,
+        }
+    }
+
+    /// Resolves a texel coordinate to one within `0..size`, or `None` if the border colour should
+    /// be used. Unless using [AddressMode::ClampToBorder], the coordinate must lie within one texel
+    /// of that range, as is the case for coordinates derived from [AddressMode::normalise]
+    #[inline(always)]
+    pub(super) fn resolve(self, coord: isize, size: usize) -> Option<usize> {
+        let size = size as isize;
+
+        let coord = match self {
+            AddressMode::Repeat => {
+                if coord < 0 {
+                    coord + size
+                } else if coord >= size {
+                    coord - size
+                } else {
+                    coord
+                }
+            }
+            // Reflecting a single texel past the edge is the same as clamping
+            AddressMode::MirroredRepeat | AddressMode::ClampToEdge => coord.clamp(0, size - 1),
+            AddressMode::ClampToBorder => {
+                if coord < 0 || coord >= size {
+                    return None;
+                }
+                coord
+            }
+        };
+
+        Some(coord as usize)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Texture {
     name: String,
-    pub levels: [MipLevel; MIP_LEVELS],
-    pub pixels: Vec<RGB>,
+    pub(crate) levels: Vec<MipLevel>,
+    /// Texels of every mip level, which the unchecked samplers index using the levels
+    pub(crate) pixels: Vec<RGBA>,
+    /// Offset added to the level of detail calculated for each sample. A positive value will result
+    /// in smaller mip levels being used, trading sharpness for less aliasing
+    pub lod_bias: f32,
+    pub filter: FilterMode,
+    address_u: AddressMode,
+    address_v: AddressMode,
+    border_colour: RGBA,
+    /// Whether the texture has power of two dimensions on both axes, allowing the faster bitwise
+    /// wrapping of texture coordinates when [RenderConfig::dim_pow_2] is enabled
+    pow_2: bool,
 }
 
 impl Named for Texture {
@@ -26,52 +116,261 @@ impl Texture {
         Ok(Self::from_bitmap(bitmap, file_name(path.as_ref()).unwrap()))
     }
 
+    /// Creates a texture from rows of pixels, starting at the top left
+    pub fn from_pixels(name: String, width: usize, height: usize, pixels: Vec<RGBA>) -> Self {
+        assert_eq!(pixels.len(), width * height, "Wrong number of pixels");
+        Self::from_bitmap(Bitmap::new(width, height, pixels), name)
+    }
+
     fn from_bitmap(bitmap: Bitmap, name: String) -> Self {
-        if DIM_POW_2 {
-            assert!(
-                bitmap.width().is_power_of_two() && bitmap.height().is_power_of_two(),
-                "Texture dimensions must be power of 2"
-            );
-        }
+        let pow_2 = bitmap.width().is_power_of_two() && bitmap.height().is_power_of_two();
 
         let levels = calculate_mip_levels(&bitmap);
-        let buffer_size = levels[MIP_LEVELS - 1].offset
-            + levels[MIP_LEVELS - 1].width * levels[MIP_LEVELS - 1].height;
+        let last_level = levels.last().expect("Texture has no mip levels");
+        let buffer_size = last_level.offset + last_level.width * last_level.height;
 
-        let mut pixels = vec![RGB::default(); buffer_size];
+        let mut pixels = vec![RGBA::default(); buffer_size];
 
         // Copy the pixels from the bitmap into the first level of the texture
         pixels[..bitmap.pixels().len()].copy_from_slice(bitmap.pixels());
 
-        // Generate rest of levels to fill buffer
-        generate_mip_maps(&levels, &mut pixels);
-
-        Self {
+        let mut texture = Self {
             levels,
             pixels,
+            lod_bias: 0.0,
+            filter: FilterMode::default(),
+            address_u: AddressMode::default(),
+            address_v: AddressMode::default(),
+            border_colour: RGBA::BLACK,
+            pow_2,
             name,
+        };
+
+        // Generate rest of levels to fill buffer
+        texture.generate_mip_maps();
+
+        texture
+    }
+
+    pub fn address_modes(&self) -> (AddressMode, AddressMode) {
+        (self.address_u, self.address_v)
+    }
+
+    pub fn border_colour(&self) -> RGBA {
+        self.border_colour
+    }
+
+    /// Sets the address modes for the horizontal and vertical axes, and the colour used by
+    /// [AddressMode::ClampToBorder]. The mip levels are regenerated, as texels at the edges are
+    /// downsampled according to these modes
+    pub fn set_address_modes(&mut self, u: AddressMode, v: AddressMode, border_colour: RGBA) {
+        self.address_u = u;
+        self.address_v = v;
+        self.border_colour = border_colour;
+
+        self.generate_mip_maps();
+    }
+
+    fn generate_mip_maps(&mut self) {
+        generate_mip_maps(
+            &self.levels,
+            &mut self.pixels,
+            self.address_u,
+            self.address_v,
+            self.border_colour,
+        );
+    }
+
+    /// Calculates the level of detail from the screen space derivatives of the texture coordinates,
+    /// such that level 0 is used when a texel maps to roughly one pixel
+    pub fn lod(&self, tex_coord_dx: Vec2f, tex_coord_dy: Vec2f) -> f32 {
+        let base = &self.levels[0];
+
+        let dx_u = tex_coord_dx.x * base.width_f;
+        let dx_v = tex_coord_dx.y * base.height_f;
+        let dy_u = tex_coord_dy.x * base.width_f;
+        let dy_v = tex_coord_dy.y * base.height_f;
+
+        let rho_sq = (dx_u * dx_u + dx_v * dx_v).max(dy_u * dy_u + dy_v * dy_v);
+
+        // log2(sqrt(x)) == 0.5 * log2(x)
+        0.5 * rho_sq.log2() + self.lod_bias
+    }
+
+    /// Width of the texture in texels, at mip level 0
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    /// Height of the texture in texels, at mip level 0
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    /// Sizes and offsets of each mip level within the pixels
+    pub fn levels(&self) -> &[MipLevel] {
+        &self.levels
+    }
+
+    pub fn pixels(&self) -> &[RGBA] {
+        &self.pixels
+    }
+
+    pub fn is_pow_2(&self) -> bool {
+        self.pow_2
+    }
+
+    /// The highest mip level that may be sampled from, limited by both the size of the texture and
+    /// the configured number of mip levels
+    pub fn max_level(&self, config: &RenderConfig) -> usize {
+        config.mip_levels.min(self.levels.len()).saturating_sub(1)
+    }
+
+    /// Whether texel coordinates can be wrapped with a bitwise AND, rather than going through the
+    /// address modes
+    #[inline(always)]
+    fn fast_wrap(&self, config: &RenderConfig) -> bool {
+        config.dim_pow_2
+            && self.pow_2
+            && self.address_u == AddressMode::Repeat
+            && self.address_v == AddressMode::Repeat
+    }
+
+    /// Samples the texture at the given level of detail, according to its filter mode
+    pub(crate) unsafe fn sample_lod_unchecked(
+        &self,
+        x: f32,
+        y: f32,
+        lod: f32,
+        config: &RenderConfig,
+    ) -> RGBA {
+        match self.filter {
+            FilterMode::Nearest => self.sample_unchecked(x, y, nearest_level(lod), config),
+
+            FilterMode::Bilinear => {
+                self.sample_bilinear_unchecked(x, y, nearest_level(lod), config)
+            }
+
+            FilterMode::Trilinear => {
+                let max_level = self.max_level(config);
+                // NaN values are clamped to level 0 by `max`
+                let lod = lod.max(0.0).min(max_level as f32);
+                let level = lod as usize;
+                let fract = lod - level as f32;
+
+                let near = self.sample_bilinear_unchecked(x, y, level, config);
+                if level == max_level || fract == 0.0 {
+                    return near;
+                }
+
+                let far = self.sample_bilinear_unchecked(x, y, level + 1, config);
+                far.blend(near, fract)
+            }
         }
     }
 
-    pub unsafe fn sample_unchecked(&self, mut x: f32, mut y: f32, level: usize) -> RGB {
-        debug_assert!(level < MIP_LEVELS);
-        let level = self.levels.get_unchecked(level);
+    pub(crate) unsafe fn sample_unchecked(
+        &self,
+        x: f32,
+        y: f32,
+        level: usize,
+        config: &RenderConfig,
+    ) -> RGBA {
+        let level = self.levels.get_unchecked(level.min(self.max_level(config)));
 
-        if !DIM_POW_2 {
-            x = x - x.floor();
-            y = y - y.floor();
+        if self.fast_wrap(config) {
+            let x = (x * level.width_f).floor() as isize as usize & (level.width - 1);
+            let y = (y * level.height_f).floor() as isize as usize & (level.height - 1);
+
+            return self.texel_unchecked(level, x, y);
         }
 
-        debug_assert!(x.is_finite() && x.abs() < usize::MAX as f32);
-        debug_assert!(y.is_finite() && y.abs() < usize::MAX as f32);
-        let mut x = (x * level.width_f).to_int_unchecked::<usize>();
-        let mut y = (y * level.height_f).to_int_unchecked::<usize>();
+        let x = self.address_u.normalise(x);
+        let y = self.address_v.normalise(y);
+        let x = (x * level.width_f).floor() as isize;
+        let y = (y * level.height_f).floor() as isize;
+
+        self.texel_or_border_unchecked(
+            level,
+            self.address_u.resolve(x, level.width),
+            self.address_v.resolve(y, level.height),
+        )
+    }
+
+    pub(crate) unsafe fn sample_bilinear_unchecked(
+        &self,
+        x: f32,
+        y: f32,
+        level: usize,
+        config: &RenderConfig,
+    ) -> RGBA {
+        let level = self.levels.get_unchecked(level.min(self.max_level(config)));
+        let fast_wrap = self.fast_wrap(config);
+
+        let (x, y) = if fast_wrap {
+            (x, y)
+        } else {
+            (self.address_u.normalise(x), self.address_v.normalise(y))
+        };
+
+        // Offset by half a texel, so that texel centres lie on integer coordinates
+        let x = x * level.width_f - 0.5;
+        let y = y * level.height_f - 0.5;
+        let x_floor = x.floor();
+        let y_floor = y.floor();
+        let fract_x = x - x_floor;
+        let fract_y = y - y_floor;
+        let x0 = x_floor as isize;
+        let y0 = y_floor as isize;
+
+        let (top_left, top_right, bottom_left, bottom_right) = if fast_wrap {
+            let mask_x = level.width - 1;
+            let mask_y = level.height - 1;
+            let (x0, x1) = (x0 as usize & mask_x, x0.wrapping_add(1) as usize & mask_x);
+            let (y0, y1) = (y0 as usize & mask_y, y0.wrapping_add(1) as usize & mask_y);
 
-        if DIM_POW_2 {
-            x &= level.width - 1;
-            y &= level.height - 1;
+            (
+                self.texel_unchecked(level, x0, y0),
+                self.texel_unchecked(level, x1, y0),
+                self.texel_unchecked(level, x0, y1),
+                self.texel_unchecked(level, x1, y1),
+            )
+        } else {
+            let x1 = self.address_u.resolve(x0.wrapping_add(1), level.width);
+            let x0 = self.address_u.resolve(x0, level.width);
+            let y1 = self.address_v.resolve(y0.wrapping_add(1), level.height);
+            let y0 = self.address_v.resolve(y0, level.height);
+
+            (
+                self.texel_or_border_unchecked(level, x0, y0),
+                self.texel_or_border_unchecked(level, x1, y0),
+                self.texel_or_border_unchecked(level, x0, y1),
+                self.texel_or_border_unchecked(level, x1, y1),
+            )
+        };
+
+        // `a.blend(b, t)` weights `a` by `t`, so this interpolates from left to right, then top to
+        // bottom
+        let top = top_right.blend(top_left, fract_x);
+        let bottom = bottom_right.blend(bottom_left, fract_x);
+        bottom.blend(top, fract_y)
+    }
+
+    #[inline(always)]
+    unsafe fn texel_or_border_unchecked(
+        &self,
+        level: &MipLevel,
+        x: Option<usize>,
+        y: Option<usize>,
+    ) -> RGBA {
+        match (x, y) {
+            (Some(x), Some(y)) => self.texel_unchecked(level, x, y),
+            _ => self.border_colour,
         }
+    }
 
+    #[inline(always)]
+    unsafe fn texel_unchecked(&self, level: &MipLevel, x: usize, y: usize) -> RGBA {
         let local_offset = y * level.width + x;
         let global_offset = level.offset + local_offset;
 
@@ -79,3 +378,265 @@ impl Texture {
         *self.pixels.get_unchecked(global_offset)
     }
 }
+
+/// Rounds a continuous level of detail to the nearest mip level
+#[inline(always)]
+fn nearest_level(lod: f32) -> usize {
+    // Negative and NaN values saturate to level 0
+    (lod + 0.5) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::linear::Vec2f;
+
+    use crate::{colour::RGBA, config::RenderConfig};
+
+    use super::{AddressMode, FilterMode, Texture};
+
+    /// Creates a square texture, then fills each mip level with a single colour, so that samples
+    /// show which level they came from
+    fn texture_with_levels(size: usize, level_colour: impl Fn(usize) -> RGBA) -> Texture {
+        let mut texture =
+            Texture::from_pixels("levels".into(), size, size, vec![RGBA::WHITE; size * size]);
+        for (i, level) in texture.levels.clone().iter().enumerate() {
+            let len = level.width * level.height;
+            texture.pixels[level.offset..level.offset + len].fill(level_colour(i));
+        }
+
+        texture
+    }
+
+    fn grey(level: usize) -> RGBA {
+        grey_f(level as f32 / 8.0)
+    }
+
+    fn grey_f(c: f32) -> RGBA {
+        RGBA::new(c, c, c, 1.0)
+    }
+
+    fn assert_near(colour: RGBA, expected: RGBA) {
+        let error = (colour.r - expected.r).abs()
+            + (colour.g - expected.g).abs()
+            + (colour.b - expected.b).abs()
+            + (colour.a - expected.a).abs();
+        assert!(error < 1e-5, "expected {expected:?}, got {colour:?}");
+    }
+
+    #[test]
+    fn lod_from_derivatives() {
+        let mut texture = texture_with_levels(16, grey);
+        let texel = 1.0 / 16.0;
+        let assert_lod = |lod: f32, expected: f32| {
+            assert!((lod - expected).abs() < 1e-5, "{lod} != {expected}");
+        };
+
+        // one texel per pixel along both axes
+        let lod = texture.lod(Vec2f::new(texel, 0.0), Vec2f::new(0.0, texel));
+        assert_lod(lod, 0.0);
+
+        // the axis that changes fastest decides the level
+        let lod = texture.lod(Vec2f::new(4.0 * texel, 0.0), Vec2f::new(0.0, texel));
+        assert_lod(lod, 2.0);
+        let lod = texture.lod(Vec2f::new(texel, 0.0), Vec2f::new(0.0, 4.0 * texel));
+        assert_lod(lod, 2.0);
+
+        // magnified textures have a negative level of detail
+        let lod = texture.lod(Vec2f::new(texel / 4.0, 0.0), Vec2f::new(0.0, texel / 4.0));
+        assert_lod(lod, -2.0);
+
+        texture.lod_bias = 0.5;
+        let lod = texture.lod(Vec2f::new(4.0 * texel, 0.0), Vec2f::new(0.0, texel));
+        assert_lod(lod, 2.5);
+    }
+
+    #[test]
+    fn nearest_level_is_sampled() {
+        // a 16x16 texture has 5 levels
+        let texture = texture_with_levels(16, grey);
+        let config = RenderConfig::default();
+        let sample = |lod: f32, config: &RenderConfig| unsafe {
+            texture.sample_lod_unchecked(0.5, 0.5, lod, config)
+        };
+
+        assert_eq!(sample(0.0, &config), grey(0));
+        assert_eq!(sample(0.4, &config), grey(0));
+        assert_eq!(sample(0.6, &config), grey(1));
+        assert_eq!(sample(2.5, &config), grey(3));
+        assert_eq!(sample(-3.0, &config), grey(0));
+        assert_eq!(sample(f32::NAN, &config), grey(0));
+        assert_eq!(sample(100.0, &config), grey(4));
+
+        // limited by the configured number of levels
+        let config = RenderConfig {
+            mip_levels: 2,
+            ..Default::default()
+        };
+        assert_eq!(sample(3.0, &config), grey(1));
+    }
+
+    #[test]
+    fn bilinear_filtering() {
+        // black and white columns, alternating
+        let pixels = (0..16)
+            .map(|i| if i % 2 == 0 { RGBA::BLACK } else { RGBA::WHITE })
+            .collect();
+        let mut texture = Texture::from_pixels("columns".into(), 4, 4, pixels);
+        texture.filter = FilterMode::Bilinear;
+
+        for dim_pow_2 in [false, true] {
+            let config = RenderConfig {
+                dim_pow_2,
+                ..Default::default()
+            };
+            let sample = |x: f32| unsafe { texture.sample_lod_unchecked(x, 0.5, 0.0, &config) };
+
+            // texel centres are sampled exactly
+            assert_near(sample(0.125), RGBA::BLACK);
+            assert_near(sample(0.375), RGBA::WHITE);
+
+            // half way between texel centres
+            assert_near(sample(0.25), grey_f(0.5));
+            assert_near(sample(0.3125), grey_f(0.75));
+
+            // wraps around from the last column to the first
+            assert_near(sample(0.0), grey_f(0.5));
+            assert_near(sample(1.0625), grey_f(0.25));
+        }
+
+        // nearest filtering takes the texel the sample falls in
+        texture.filter = FilterMode::Nearest;
+        let config = RenderConfig::default();
+        assert_eq!(
+            unsafe { texture.sample_lod_unchecked(0.3125, 0.5, 0.0, &config) },
+            RGBA::WHITE
+        );
+    }
+
+    #[test]
+    fn trilinear_blends_levels() {
+        let mut texture = texture_with_levels(16, grey);
+        texture.filter = FilterMode::Trilinear;
+        let config = RenderConfig::default();
+        let sample = |lod: f32, config: &RenderConfig| unsafe {
+            texture.sample_lod_unchecked(0.5, 0.5, lod, config)
+        };
+
+        assert_near(sample(0.0, &config), grey(0));
+        assert_near(sample(1.0, &config), grey(1));
+        assert_near(sample(1.25, &config), grey_f(1.25 / 8.0));
+        assert_near(sample(2.5, &config), grey_f(2.5 / 8.0));
+
+        // clamped to the first and last levels
+        assert_near(sample(-1.0, &config), grey(0));
+        assert_near(sample(f32::NAN, &config), grey(0));
+        assert_near(sample(100.0, &config), grey(4));
+
+        let config = RenderConfig {
+            mip_levels: 2,
+            ..Default::default()
+        };
+        assert_near(sample(1.5, &config), grey(1));
+    }
+
+    #[test]
+    fn address_mode_normalise() {
+        let normalise = |mode: AddressMode, coords: [f32; 5]| coords.map(|x| mode.normalise(x));
+        let coords = [0.25, 1.25, -0.25, 2.25, -1.25];
+
+        assert_eq!(
+            normalise(AddressMode::Repeat, coords),
+            [0.25, 0.25, 0.75, 0.25, 0.75]
+        );
+        // every other repetition is flipped, so the texture is continuous across each edge
+        assert_eq!(
+            normalise(AddressMode::MirroredRepeat, coords),
+            [0.25, 0.75, 0.25, 0.25, 0.75]
+        );
+        assert_eq!(
+            normalise(AddressMode::ClampToEdge, coords),
+            [0.25, 1.0, 0.0, 1.0, 0.0]
+        );
+        // left for `resolve` to detect the border
+        assert_eq!(normalise(AddressMode::ClampToBorder, coords), coords);
+    }
+
+    #[test]
+    fn address_mode_resolve() {
+        let resolve = |mode: AddressMode| [-1, 0, 3, 4].map(|x| mode.resolve(x, 4));
+
+        assert_eq!(
+            resolve(AddressMode::Repeat),
+            [Some(3), Some(0), Some(3), Some(0)]
+        );
+        // reflecting across an edge lands on the edge texel
+        assert_eq!(
+            resolve(AddressMode::MirroredRepeat),
+            [Some(0), Some(0), Some(3), Some(3)]
+        );
+        assert_eq!(
+            resolve(AddressMode::ClampToEdge),
+            [Some(0), Some(0), Some(3), Some(3)]
+        );
+        assert_eq!(
+            resolve(AddressMode::ClampToBorder),
+            [None, Some(0), Some(3), None]
+        );
+        assert_eq!(AddressMode::ClampToBorder.resolve(-100, 4), None);
+        assert_eq!(AddressMode::ClampToBorder.resolve(100, 4), None);
+    }
+
+    #[test]
+    fn address_modes_when_sampling() {
+        // a white column on the right, and black elsewhere
+        let pixels = (0..16)
+            .map(|i| if i % 4 == 3 { RGBA::WHITE } else { RGBA::BLACK })
+            .collect();
+        let mut texture = Texture::from_pixels("column".into(), 4, 4, pixels);
+        let config = RenderConfig::default();
+        let border = RGBA::new(0.0, 0.0, 1.0, 0.0);
+
+        let sample = |texture: &Texture, x: f32| unsafe {
+            texture.sample_lod_unchecked(x, 0.5, 0.0, &config)
+        };
+
+        texture.set_address_modes(AddressMode::ClampToBorder, AddressMode::Repeat, border);
+        assert_eq!(sample(&texture, 0.875), RGBA::WHITE);
+        assert_eq!(sample(&texture, 1.125), border);
+        assert_eq!(sample(&texture, -0.125), border);
+
+        // just past the right edge, half way between the white column and the border
+        texture.filter = FilterMode::Bilinear;
+        assert_near(sample(&texture, 1.0), RGBA::new(0.5, 0.5, 1.0, 0.5));
+        // while the left edge is between black and the border
+        assert_near(sample(&texture, 0.0), RGBA::new(0.0, 0.0, 0.5, 0.5));
+
+        texture.filter = FilterMode::Nearest;
+        texture.set_address_modes(AddressMode::MirroredRepeat, AddressMode::Repeat, border);
+        assert_eq!(sample(&texture, 1.125), RGBA::WHITE);
+        assert_eq!(sample(&texture, 1.875), RGBA::BLACK);
+        assert_eq!(sample(&texture, -0.125), RGBA::BLACK);
+    }
+
+    #[test]
+    fn mip_generation_follows_address_modes() {
+        // a white column on the right, and black elsewhere
+        let pixels = (0..16)
+            .map(|i| if i % 4 == 3 { RGBA::WHITE } else { RGBA::BLACK })
+            .collect();
+        let mut texture = Texture::from_pixels("column".into(), 4, 4, pixels);
+        // the top left texel of the second level is downsampled from a 3x3 box, one column of
+        // which lies past the left edge
+        let top_left = |texture: &Texture| texture.pixels[texture.levels[1].offset];
+
+        // wraps around to the white column
+        assert_near(top_left(&texture), grey_f(1.0 / 3.0));
+
+        texture.set_address_modes(AddressMode::ClampToEdge, AddressMode::Repeat, RGBA::BLACK);
+        assert_near(top_left(&texture), RGBA::BLACK);
+
+        let border = RGBA::new(0.0, 0.0, 1.0, 1.0);
+        texture.set_address_modes(AddressMode::ClampToBorder, AddressMode::Repeat, border);
+        assert_near(top_left(&texture), RGBA::new(0.0, 0.0, 1.0 / 3.0, 1.0));
+    }
+}
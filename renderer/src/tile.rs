@@ -1,21 +1,98 @@
-use maths::{geometry::Shape, linear::Vec2f};
+#[cfg(feature = "simd")]
+use std::array;
+use std::iter;
+
+use maths::{
+    geometry::Shape,
+    linear::{Mat4f, Vec2f, Vec3f},
+};
+#[cfg(feature = "multithreaded")]
+use rayon::{prelude::*, ThreadPool};
 
 use crate::{
-    asset_manager::AssetStore,
-    colour::RGB,
-    model::ProjectedTriangle,
+    asset_manager::{AssetId, AssetManager},
+    colour::{RGB, RGBA},
+    config::{Fog, Msaa, RenderConfig},
+    framebuffer::Framebuffer,
+    light::{blinn_phong, fresnel_schlick, Specular, Surface, ViewLight},
+    model::{transform_direction, AlphaMode, LightingMode, ProjectedTriangle, ShadingMode},
+    raster::EdgeValues,
     renderer::RendererState,
     sat,
-    texture::Texture,
-    util::{mip_level, normalise_depth},
-    DEBUG_TILES, TILE_HEIGHT, TILE_WIDTH,
+    shader::{Fragment, FragmentShader, Uniforms, Varyings},
+    shadow::ShadowMap,
+    texture::{Cubemap, Texture},
+    util::normalise_depth,
 };
 
+#[cfg(feature = "simd")]
+use crate::simd::F32x4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Cover {
     Partial,
     Full,
 }
 
+impl Cover {
+    fn debug_colour(self) -> RGB {
+        match self {
+            Cover::Partial => RGB::MAGENTA,
+            Cover::Full => RGB::CYAN,
+        }
+    }
+}
+
+/// Triangles to draw to a tile, as indices into the slice being rendered, and how much of the tile
+/// each covers
+type TriangleList = Vec<(Cover, u32)>;
+
+/// The cover of a triangle over a tile as a type, so the pixel loop is compiled separately for each,
+/// rather than checking the cover at every pixel
+trait CoverTest {
+    /// Mask of the samples of a pixel that are inside the triangle
+    fn coverage(setup: &TriangleSetup, values: &EdgeValues, samples: usize) -> u32;
+
+    /// Mask of four horizontally adjacent pixels that are inside the triangle, without multisampling
+    #[cfg(feature = "simd")]
+    fn coverage_x4(setup: &TriangleSetup, values: &EdgeValues) -> u32;
+}
+
+struct FullCover;
+
+impl CoverTest for FullCover {
+    // every sample is in the triangle for full cover, so coverage is not tested
+    #[inline(always)]
+    fn coverage(setup: &TriangleSetup, _values: &EdgeValues, _samples: usize) -> u32 {
+        setup.full_coverage
+    }
+
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn coverage_x4(_setup: &TriangleSetup, _values: &EdgeValues) -> u32 {
+        0b1111
+    }
+}
+
+struct PartialCover;
+
+impl CoverTest for PartialCover {
+    // check which samples are in triangle, following the top-left fill rule
+    #[inline(always)]
+    fn coverage(setup: &TriangleSetup, values: &EdgeValues, samples: usize) -> u32 {
+        setup
+            .triangle
+            .edges
+            .coverage(values, &setup.edge_offsets[..samples])
+    }
+
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn coverage_x4(setup: &TriangleSetup, values: &EdgeValues) -> u32 {
+        setup.triangle.edges.coverage_x4(values)
+    }
+}
+
 struct Bounds {
     min_x: usize,
     min_y: usize,
@@ -26,32 +103,100 @@ struct Bounds {
 struct Tile {
     bounds: Bounds,
     points: [Vec2f; 4],
-    triangles: Vec<(Cover, *const ProjectedTriangle)>,
+    /// Triangles overlapping the tile, in the order they are drawn
+    triangles: TriangleList,
+    /// Upper bound on the depth of every sample in the tile. Triangles which are entirely behind
+    /// this can't pass the depth test anywhere in the tile, so are skipped
+    max_depth: f32,
 }
 
-#[derive(Default)]
 pub struct TileRenderer {
     tiles_x: usize,
     tiles_y: usize,
+    tile_width: usize,
+    tile_height: usize,
     tiles: Vec<Tile>,
+    /// Per chunk lists of triangles overlapping each tile, used while binning
+    bins: Vec<Vec<TriangleList>>,
 
+    /// Renders tiles on a pool of worker threads, or is `None` when threading is switched off
     #[cfg(feature = "multithreaded")]
-    threaded: multithreading::ThreadedRenderer,
+    threaded: Option<multithreading::ThreadedRenderer>,
 }
 
+/// The minimum number of triangles binned together, below which it isn't worth splitting the work
+/// between threads
+const MIN_BIN_CHUNK: usize = 512;
+
 impl TileRenderer {
-    pub fn update_viewport(&mut self, width: usize, height: usize) {
-        self.tiles_x = (width + TILE_WIDTH - 1) / TILE_WIDTH;
-        self.tiles_y = (height + TILE_HEIGHT - 1) / TILE_HEIGHT;
+    /// Fails if the pool of worker threads can't be created
+    pub fn new(config: &RenderConfig) -> Result<Self, anyhow::Error> {
+        let mut renderer = Self::unthreaded(config);
+        renderer.set_threads(config.threads)?;
+        Ok(renderer)
+    }
+
+    /// Creates a renderer without any worker threads, until it's given some with
+    /// [TileRenderer::set_threads] or [TileRenderer::share_threads]
+    pub fn unthreaded(config: &RenderConfig) -> Self {
+        Self {
+            tiles_x: 0,
+            tiles_y: 0,
+            tile_width: config.tile_width,
+            tile_height: config.tile_height,
+            tiles: Vec::new(),
+            bins: Vec::new(),
+
+            #[cfg(feature = "multithreaded")]
+            threaded: None,
+        }
+    }
+
+    /// Rebuilds the pool of worker threads with a new number of threads. A value of 1 switches off
+    /// threading. If the new pool can't be created, the current one is kept
+    #[cfg(feature = "multithreaded")]
+    pub fn set_threads(&mut self, num_threads: usize) -> Result<(), anyhow::Error> {
+        self.threaded = multithreading::ThreadedRenderer::new(num_threads)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "multithreaded"))]
+    pub fn set_threads(&mut self, _num_threads: usize) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    /// Draws on the same pool of worker threads as another renderer, rather than a pool of its own
+    #[cfg(feature = "multithreaded")]
+    pub fn share_threads(&mut self, other: &TileRenderer) {
+        self.threaded = other.threaded.clone();
+    }
+
+    #[cfg(not(feature = "multithreaded"))]
+    pub fn share_threads(&mut self, _other: &TileRenderer) {}
+
+    /// The pool of worker threads, also used for work outside of the tile renderer. This is `None`
+    /// when threading is switched off
+    #[cfg(feature = "multithreaded")]
+    pub fn thread_pool(&self) -> Option<&ThreadPool> {
+        self.threaded
+            .as_ref()
+            .map(multithreading::ThreadedRenderer::thread_pool)
+    }
+
+    pub fn update_viewport(&mut self, width: usize, height: usize, config: &RenderConfig) {
+        self.tile_width = config.tile_width;
+        self.tile_height = config.tile_height;
+        self.tiles_x = (width + self.tile_width - 1) / self.tile_width;
+        self.tiles_y = (height + self.tile_height - 1) / self.tile_height;
         self.tiles.clear();
 
         for y in 0..self.tiles_y {
             for x in 0..self.tiles_x {
                 let bounds = Bounds {
-                    min_x: x * TILE_WIDTH,
-                    min_y: y * TILE_HEIGHT,
-                    max_x: ((x + 1) * TILE_WIDTH).min(width),
-                    max_y: ((y + 1) * TILE_HEIGHT).min(height),
+                    min_x: x * self.tile_width,
+                    min_y: y * self.tile_height,
+                    max_x: ((x + 1) * self.tile_width).min(width),
+                    max_y: ((y + 1) * self.tile_height).min(height),
                 };
 
                 let points = [
@@ -65,482 +210,1886 @@ impl TileRenderer {
                     bounds,
                     points,
                     triangles: Vec::new(),
+                    max_depth: f32::MAX,
                 });
             }
         }
     }
 
+    /// Resets the depth of every tile, to be called when the depth buffer is cleared
+    pub fn clear_depth(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            tile.max_depth = f32::MAX;
+        }
+    }
+
     pub fn render(
         &mut self,
         state: &mut RendererState,
-        textures: &AssetStore<Texture>,
+        assets: &AssetManager,
+        triangles: &[ProjectedTriangle],
+    ) {
+        self.place_triangles(triangles);
+
+        let frame = FrameSetup {
+            config: &state.config,
+            msaa: state.config.msaa,
+            shading: Some(Shading {
+                uniforms: &state.uniforms,
+                lights: &state.lights,
+                shadow_map: state.shadow_map.as_ref(),
+                fog_colour: state.fog_colour(),
+                camera_to_world: state.camera.inverse_view_transform(),
+                pixel_angle: state.camera_view().pixel_angle(),
+                assets,
+            }),
+            triangles,
+            width: state.framebuffer.width(),
+        };
+
+        self.draw_bands(&frame, &mut state.framebuffer);
+    }
+
+    /// Draws only the depth of the triangles, without multisampling, skipping shading and texture
+    /// sampling entirely. Transparency is ignored, so every triangle is drawn as though it's opaque
+    pub fn render_depth(
+        &mut self,
+        framebuffer: &mut Framebuffer,
+        config: &RenderConfig,
         triangles: &[ProjectedTriangle],
     ) {
         self.place_triangles(triangles);
 
+        let frame = FrameSetup {
+            config,
+            msaa: Msaa::Off,
+            shading: None,
+            triangles,
+            width: framebuffer.width(),
+        };
+
+        self.draw_bands(&frame, framebuffer);
+    }
+
+    /// Draws the triangles binned to each tile, one band of tiles at a time
+    fn draw_bands(&mut self, frame: &FrameSetup, framebuffer: &mut Framebuffer) {
+        let bands = Band::split(framebuffer, &mut self.tiles, self.tiles_x, self.tile_height);
+
         #[cfg(feature = "multithreaded")]
-        self.threaded
-            .render(&mut state.framebuffer, textures, &mut self.tiles);
+        if let Some(threaded) = &self.threaded {
+            threaded.render(frame, bands);
+            return;
+        }
+
+        bands.for_each(|band| band.render(frame));
+    }
+
+    /// Adds each triangle to the lists of the tiles it overlaps. The triangles are split into
+    /// contiguous chunks which are binned separately, in parallel when multithreaded, then merged
+    /// in chunk order, so each tile's list is in the same order as `triangles`
+    fn place_triangles(&mut self, triangles: &[ProjectedTriangle]) {
+        let mut bins = std::mem::take(&mut self.bins);
+
+        let chunk_size = triangles
+            .len()
+            .div_ceil(self.bin_chunks())
+            .max(MIN_BIN_CHUNK);
+        bins.resize_with(triangles.len().div_ceil(chunk_size), Vec::new);
+
+        let bin_chunk = |(chunk, (chunk_bins, triangles)): (
+            usize,
+            (&mut Vec<TriangleList>, &[ProjectedTriangle]),
+        )| {
+            chunk_bins.resize_with(self.tiles.len(), Vec::new);
+            chunk_bins.iter_mut().for_each(Vec::clear);
+
+            let offset = chunk * chunk_size;
+            for (i, triangle) in triangles.iter().enumerate() {
+                self.bin_triangle(triangle, |tile, cover| {
+                    chunk_bins[tile].push((cover, (offset + i) as u32));
+                });
+            }
+        };
+
+        #[cfg(feature = "multithreaded")]
+        match self.thread_pool() {
+            Some(pool) => pool.install(|| {
+                bins.par_iter_mut()
+                    .zip(triangles.par_chunks(chunk_size))
+                    .enumerate()
+                    .for_each(bin_chunk)
+            }),
+            None => bins
+                .iter_mut()
+                .zip(triangles.chunks(chunk_size))
+                .enumerate()
+                .for_each(bin_chunk),
+        }
 
         #[cfg(not(feature = "multithreaded"))]
-        for tile in self.tiles.iter_mut() {
-            for (cover, triangle) in tile.triangles.drain(..) {
-                let triangle = unsafe { &*triangle };
-
-                match cover {
-                    Cover::Full => {
-                        Self::render_full_tile(
-                            state,
-                            textures,
-                            triangle,
-                            &tile.points,
-                            &tile.bounds,
-                        );
-                    }
-                    Cover::Partial => {
-                        Self::render_partial_tile(
-                            state,
-                            textures,
-                            triangle,
-                            &tile.points,
-                            &tile.bounds,
-                        );
-                    }
+        bins.iter_mut()
+            .zip(triangles.chunks(chunk_size))
+            .enumerate()
+            .for_each(bin_chunk);
+
+        let merge = |(index, tile): (usize, &mut Tile)| {
+            for chunk_bins in bins.iter() {
+                tile.triangles.extend_from_slice(&chunk_bins[index]);
+            }
+        };
+
+        #[cfg(feature = "multithreaded")]
+        match &self.threaded {
+            Some(threaded) => threaded
+                .thread_pool()
+                .install(|| self.tiles.par_iter_mut().enumerate().for_each(merge)),
+            None => self.tiles.iter_mut().enumerate().for_each(merge),
+        }
+
+        #[cfg(not(feature = "multithreaded"))]
+        self.tiles.iter_mut().enumerate().for_each(merge);
+
+        self.bins = bins;
+    }
+
+    /// The number of chunks to split triangles into for binning
+    #[cfg(feature = "multithreaded")]
+    fn bin_chunks(&self) -> usize {
+        self.thread_pool()
+            .map_or(1, ThreadPool::current_num_threads)
+    }
+
+    #[cfg(not(feature = "multithreaded"))]
+    fn bin_chunks(&self) -> usize {
+        1
+    }
+
+    /// Finds the tiles overlapped by a triangle, calling `bin` with the index of each tile and how
+    /// much of it is covered
+    #[inline(always)]
+    fn bin_triangle(&self, triangle: &ProjectedTriangle, mut bin: impl FnMut(usize, Cover)) {
+        let min_depth = triangle.min_depth();
+        let triangle_aabb = triangle.bounds();
+        // clamp to the screen before converting, as triangles may extend past any edge
+        let tile_range = |min: f32, max: f32, tile_size: usize, tiles: usize| {
+            let tile_size = tile_size as f32;
+            let tiles = tiles as f32;
+            let start = (min / tile_size).floor().clamp(0.0, tiles) as usize;
+            let end = (max / tile_size).ceil().clamp(0.0, tiles) as usize;
+            start..end
+        };
+        let x_range = tile_range(
+            triangle_aabb.min.x,
+            triangle_aabb.max.x,
+            self.tile_width,
+            self.tiles_x,
+        );
+        let y_range = tile_range(
+            triangle_aabb.min.y,
+            triangle_aabb.max.y,
+            self.tile_height,
+            self.tiles_y,
+        );
+
+        for y in y_range {
+            for x in x_range.clone() {
+                let index = y * self.tiles_x + x;
+                let tile = &self.tiles[index];
+
+                // skip tile if the triangle is behind everything already drawn to it
+                if min_depth >= tile.max_depth {
+                    continue;
+                }
+
+                match sat::overlap_test(
+                    &tile.points,
+                    triangle.vertices.points(),
+                    &triangle.sat_edges,
+                ) {
+                    sat::Overlap::None => {}
+
+                    sat::Overlap::Partial => bin(index, Cover::Partial),
+
+                    sat::Overlap::Full => bin(index, Cover::Full),
                 }
             }
         }
     }
+}
 
-    fn place_triangles(&mut self, triangles: &[ProjectedTriangle]) {
-        for triangle in triangles.iter() {
-            let triangle_aabb = triangle.bounds();
-            let x_min = (triangle_aabb.min.x as usize / TILE_WIDTH).max(0);
-            let x_max = ((triangle_aabb.max.x.ceil() as usize + TILE_WIDTH - 1) / TILE_WIDTH)
-                .min(self.tiles_x);
-            let y_min = (triangle_aabb.min.y as usize / TILE_HEIGHT).max(0);
-            let y_max = ((triangle_aabb.max.y.ceil() as usize + TILE_HEIGHT - 1) / TILE_HEIGHT)
-                .min(self.tiles_y);
-
-            for y in y_min..y_max {
-                for x in x_min..x_max {
-                    let index = y * self.tiles_x + x;
-                    let tile = &mut self.tiles[index];
-
-                    match sat::overlap_test(
-                        &tile.points,
-                        triangle.vertices.points(),
-                        &triangle.sat_edges,
-                    ) {
-                        sat::Overlap::None => {}
-
-                        sat::Overlap::Partial => {
-                            tile.triangles.push((Cover::Partial, triangle));
-                        }
+/// Values that are constant while rendering a set of triangles
+struct FrameSetup<'a> {
+    config: &'a RenderConfig,
+    /// Multisampling of the framebuffer being drawn to, which is always off for depth only passes
+    msaa: Msaa,
+    /// Everything needed to colour pixels, or `None` when only depth is drawn
+    shading: Option<Shading<'a>>,
+    triangles: &'a [ProjectedTriangle],
+    /// Width of the framebuffer, in pixels
+    width: usize,
+}
 
-                        sat::Overlap::Full => {
-                            tile.triangles.push((Cover::Full, triangle));
-                        }
+#[derive(Clone, Copy)]
+struct Shading<'a> {
+    uniforms: &'a Uniforms,
+    lights: &'a [ViewLight],
+    shadow_map: Option<&'a ShadowMap>,
+    /// Colour that distant surfaces fade into, with the clear colour filled in if unset
+    fog_colour: RGB,
+    /// Rotates reflections from view space into world space, to sample environment maps
+    camera_to_world: Mat4f,
+    /// Angle between the rays through neighbouring pixels, for choosing environment map mip levels
+    pixel_angle: f32,
+    assets: &'a AssetManager,
+}
+
+/// A row of tiles, along with the colour and depth samples of the rows of pixels they cover. Each
+/// band can be drawn independently of the others
+struct Band<'a> {
+    colour: &'a mut [RGB],
+    depth: &'a mut [f32],
+    tiles: &'a mut [Tile],
+}
+
+impl<'a> Band<'a> {
+    fn split(
+        framebuffer: &'a mut Framebuffer,
+        tiles: &'a mut [Tile],
+        tiles_x: usize,
+        tile_height: usize,
+    ) -> impl Iterator<Item = Band<'a>> {
+        let band_len = framebuffer.width() * tile_height * framebuffer.samples();
+        let (colour_buffer, depth_buffer) = framebuffer.samples_mut();
+
+        // depth only framebuffers have no colour samples, so their bands are given empty slices
+        colour_buffer
+            .chunks_mut(band_len.max(1))
+            .chain(iter::repeat_with(Default::default))
+            .zip(depth_buffer.chunks_mut(band_len.max(1)))
+            .zip(tiles.chunks_mut(tiles_x.max(1)))
+            .map(|((colour, depth), tiles)| Band {
+                colour,
+                depth,
+                tiles,
+            })
+    }
+
+    /// Estimate of the time taken to draw the band, from the number of triangles binned to it
+    #[cfg(feature = "multithreaded")]
+    fn cost(&self) -> usize {
+        self.tiles.iter().map(|tile| tile.triangles.len()).sum()
+    }
+
+    fn render(self, frame: &FrameSetup) {
+        for tile in self.tiles.iter_mut() {
+            render_tile(frame, tile, self.colour, self.depth);
+        }
+    }
+}
+
+/// Draws every triangle binned to a tile, given the samples of the band containing the tile. This
+/// is the same whether or not tiles are drawn in parallel
+fn render_tile(
+    frame: &FrameSetup,
+    tile: &mut Tile,
+    colour_band: &mut [RGB],
+    depth_band: &mut [f32],
+) {
+    let mut last_cover = None;
+
+    for (cover, index) in tile.triangles.drain(..) {
+        let triangle = &frame.triangles[index as usize];
+
+        // skip triangle if it's behind everything drawn to the tile so far
+        if triangle.min_depth() >= tile.max_depth {
+            continue;
+        }
+
+        let setup = TriangleSetup::new(triangle, frame);
+
+        match cover {
+            Cover::Full => {
+                setup.draw_tile::<FullCover>(frame, &tile.bounds, colour_band, depth_band);
+                tile.max_depth = covered_max_depth(tile.max_depth, &setup);
+            }
+            Cover::Partial => {
+                setup.draw_tile::<PartialCover>(frame, &tile.bounds, colour_band, depth_band);
+            }
+        }
+
+        last_cover = Some(cover);
+    }
+
+    if !frame.config.debug_tiles || frame.shading.is_none() {
+        return;
+    }
+
+    // marks the centre of the tile with the cover of the last triangle drawn to it
+    if let Some(cover) = last_cover {
+        let bounds = &tile.bounds;
+        let samples = frame.config.msaa.samples();
+        let index =
+            ((bounds.max_y - bounds.min_y) / 2) * frame.width + (bounds.min_x + bounds.max_x) / 2;
+        colour_band[index * samples..(index + 1) * samples].fill(cover.debug_colour());
+    }
+}
+
+/// Calculates a tile's new maximum depth after drawing a triangle that fully covers it. Opaque
+/// triangles leave every sample no further away than the triangle's furthest vertex, unless they
+/// have a fragment shader, which may discard pixels and leave holes
+#[inline(always)]
+fn covered_max_depth(max_depth: f32, setup: &TriangleSetup) -> f32 {
+    let triangle = setup.triangle;
+
+    if triangle.alpha_mode == AlphaMode::Opaque && setup.fragment_shader.is_none() {
+        max_depth.min(triangle.max_depth())
+    } else {
+        max_depth
+    }
+}
+
+/// Values that are constant while drawing a triangle to a tile
+struct TriangleSetup<'a> {
+    triangle: &'a ProjectedTriangle,
+    /// The material's diffuse texture
+    texture: Option<&'a Texture>,
+    ambient_texture: Option<&'a Texture>,
+    specular_texture: Option<&'a Texture>,
+    normal_texture: Option<&'a Texture>,
+    /// The material's base colour and opacity, which tint the shaded colour
+    base_colour: RGBA,
+    specular: Specular,
+    fragment_shader: Option<(&'a dyn FragmentShader, &'a Uniforms)>,
+    lights: &'a [ViewLight],
+    shadow_map: Option<&'a ShadowMap>,
+    fog_colour: RGB,
+    /// The material's environment map, along with the level of detail to sample it at
+    environment: Option<(&'a Cubemap, f32)>,
+    reflectivity: f32,
+    camera_to_world: Mat4f,
+    /// Change in the edge function values from a pixel's centre to each sample position
+    edge_offsets: [EdgeValues; Msaa::MAX_SAMPLES],
+    /// Change in inverse depth from a pixel's centre to each sample position
+    depth_inv_offsets: [f32; Msaa::MAX_SAMPLES],
+    /// Coverage mask with a bit set for every sample
+    full_coverage: u32,
+    /// Change in barycentric coordinates for a step of one pixel to the right
+    #[cfg(feature = "simd")]
+    barycentric_dx: Vec3f,
+}
+
+impl<'a> TriangleSetup<'a> {
+    fn new(triangle: &'a ProjectedTriangle, frame: &FrameSetup<'a>) -> Self {
+        let positions = frame.msaa.sample_positions();
+
+        // Inverse depth is linear in screen space, so the offsets are the same for every pixel
+        let mut depth_inv_offsets = [0.0; Msaa::MAX_SAMPLES];
+        for (offset, position) in depth_inv_offsets.iter_mut().zip(positions) {
+            *offset = triangle.tex_coords_depth_dx.z * position.x
+                + triangle.tex_coords_depth_dy.z * position.y;
+        }
+
+        // nothing is looked up when only drawing depth
+        let shading = frame.shading;
+        let material = shading.and_then(|shading| {
+            triangle
+                .material_id
+                .and_then(|id| shading.assets.materials.get(id))
+        });
+        let texture = |texture_id: Option<AssetId<Texture>>| {
+            shading.and_then(|shading| texture_id.and_then(|id| shading.assets.textures.get(id)))
+        };
+
+        Self {
+            triangle,
+            texture: texture(material.and_then(|material| material.diffuse_texture)),
+            ambient_texture: texture(material.and_then(|material| material.ambient_texture)),
+            specular_texture: texture(material.and_then(|material| material.specular_texture)),
+            normal_texture: texture(material.and_then(|material| material.normal_texture)),
+            base_colour: material.map_or(RGBA::WHITE, |material| {
+                let mut base_colour = RGBA::from(material.base_colour);
+                base_colour.a = material.opacity;
+                base_colour
+            }),
+            specular: material.map_or(Specular::default(), |material| material.specular),
+            fragment_shader: shading.and_then(|shading| {
+                triangle
+                    .shader_id
+                    .and_then(|id| shading.assets.shaders.get(id))
+                    .and_then(|shader| shader.fragment.as_deref())
+                    .map(|fragment_shader| (fragment_shader, shading.uniforms))
+            }),
+            lights: shading.map_or(&[], |shading| shading.lights),
+            shadow_map: shading.and_then(|shading| shading.shadow_map),
+            fog_colour: shading.map_or(RGB::BLACK, |shading| shading.fog_colour),
+            environment: shading.and_then(|shading| {
+                material
+                    .and_then(|material| material.environment_map)
+                    .and_then(|id| shading.assets.cubemaps.get(id))
+                    .map(|cubemap| (cubemap, cubemap.lod(shading.pixel_angle)))
+            }),
+            reflectivity: material.map_or(0.0, |material| material.reflectivity),
+            camera_to_world: shading.map_or(Mat4f::IDENTITY, |shading| shading.camera_to_world),
+            edge_offsets: triangle.edges.sample_offsets(positions),
+            depth_inv_offsets,
+            full_coverage: (1 << positions.len()) - 1,
+            #[cfg(feature = "simd")]
+            barycentric_dx: triangle.edges.barycentric_step_x(),
+        }
+    }
+
+    /// Draws the triangle to a tile, given the colour and depth samples of the band of rows
+    /// containing the tile
+    #[inline(always)]
+    fn draw_tile<C: CoverTest>(
+        &self,
+        frame: &FrameSetup,
+        tile_bounds: &Bounds,
+        colour_band: &mut [RGB],
+        depth_band: &mut [f32],
+    ) {
+        if frame.shading.is_none() {
+            return self.draw_depth_tile::<C>(frame, tile_bounds, depth_band);
+        }
+
+        let samples = frame.msaa.samples();
+        let row_len = (tile_bounds.max_x - tile_bounds.min_x) * samples;
+        let edges = &self.triangle.edges;
+        let mut row = edges.values_at(tile_bounds.min_x, tile_bounds.min_y);
+
+        for y in 0..tile_bounds.max_y - tile_bounds.min_y {
+            let start = (y * frame.width + tile_bounds.min_x) * samples;
+
+            self.draw_row::<C>(
+                frame.config,
+                row,
+                &mut colour_band[start..start + row_len],
+                &mut depth_band[start..start + row_len],
+            );
+
+            edges.step_y(&mut row);
+        }
+    }
+
+    /// Draws only the triangle's depth to a tile, given the depth of the band of rows containing the
+    /// tile, with a single sample per pixel
+    #[inline(always)]
+    fn draw_depth_tile<C: CoverTest>(
+        &self,
+        frame: &FrameSetup,
+        tile_bounds: &Bounds,
+        depth_band: &mut [f32],
+    ) {
+        let row_len = tile_bounds.max_x - tile_bounds.min_x;
+        let edges = &self.triangle.edges;
+        let mut row = edges.values_at(tile_bounds.min_x, tile_bounds.min_y);
+
+        for y in 0..tile_bounds.max_y - tile_bounds.min_y {
+            let start = y * frame.width + tile_bounds.min_x;
+            let mut values = row;
+
+            for sample_depth in depth_band[start..start + row_len].iter_mut() {
+                if C::coverage(self, &values, 1) != 0 {
+                    let depth = 1.0 / edges.barycentric(&values).dot(self.triangle.depth_inv);
+                    if depth < *sample_depth {
+                        *sample_depth = depth;
                     }
                 }
+
+                edges.step_x(&mut values);
             }
+
+            edges.step_y(&mut row);
         }
     }
 
-    fn render_full_tile(
-        state: &mut RendererState,
-        textures: &AssetStore<Texture>,
-        triangle: &ProjectedTriangle,
-        tile_points: &[Vec2f; 4],
-        tile_bounds: &Bounds,
+    /// Draws the triangle across one row of a tile, given the colour and depth samples of the
+    /// pixels in that row, and the edge function values at the first pixel
+    #[inline(always)]
+    fn draw_row<C: CoverTest>(
+        &self,
+        config: &RenderConfig,
+        mut values: EdgeValues,
+        colour_row: &mut [RGB],
+        depth_row: &mut [f32],
     ) {
-        let texture = textures.get(triangle.texture_id.unwrap()).unwrap();
+        let samples = config.msaa.samples();
+
+        // fragment shaders, per pixel lighting and reflections are run one pixel at a time
+        #[cfg(feature = "simd")]
+        if samples == 1
+            && self.fragment_shader.is_none()
+            && self.triangle.lighting == LightingMode::Vertex
+            && self.environment.is_none()
+        {
+            return self.draw_row_x4::<C>(config, values, colour_row, depth_row);
+        }
+
+        let edges = &self.triangle.edges;
 
-        let mut index = tile_bounds.min_y * state.width() + tile_bounds.min_x;
-        let mut point = tile_points[0] + 0.5;
+        for (colour_samples, depth_samples) in colour_row
+            .chunks_exact_mut(samples)
+            .zip(depth_row.chunks_exact_mut(samples))
+        {
+            let coverage = C::coverage(self, &values, samples);
 
-        for y in tile_bounds.min_y..tile_bounds.max_y {
-            for x in tile_bounds.min_x..tile_bounds.max_x {
+            if coverage != 0 {
                 // get barycentric coordinates
-                let barycentric = triangle
-                    .vertices
-                    .barycentric_from_inv_area(point, triangle.two_area_inv);
+                let barycentric = edges.barycentric(&values);
 
-                // check if coordinates are in triangle (can be skipped for full cover)
+                self.write_samples(config, barycentric, coverage, colour_samples, depth_samples);
+            }
 
-                // calculate depth
-                let depth = 1.0 / barycentric.dot(triangle.depth_inv);
+            edges.step_x(&mut values);
+        }
+    }
 
-                // check depth in
-                if depth < unsafe { *state.depth().get_unchecked(index) } {
-                    let col_a = triangle.col_depth[0] * barycentric.x;
-                    let col_b = triangle.col_depth[1] * barycentric.y;
-                    let col_c = triangle.col_depth[2] * barycentric.z;
-                    let colour = (col_a + col_b + col_c) * depth;
-
-                    let u = (triangle.tex_coords_depth[0].x * barycentric.x
-                        + triangle.tex_coords_depth[1].x * barycentric.y
-                        + triangle.tex_coords_depth[2].x * barycentric.z)
-                        * depth;
-                    let v = (triangle.tex_coords_depth[0].y * barycentric.x
-                        + triangle.tex_coords_depth[1].y * barycentric.y
-                        + triangle.tex_coords_depth[2].y * barycentric.z)
-                        * depth;
-
-                    // let normal_depth = normalise_depth(depth);
-                    // let mip_level = mip_level(normal_depth, 0.0);
-
-                    let colour = unsafe { texture.sample_unchecked(u, v, 0) };
-
-                    // SAFETY: Tile's integer bounds are within screen bounds
-                    unsafe {
-                        *state.pixels_mut().get_unchecked_mut(index) = RGB::from(colour);
-                        *state.depth_mut().get_unchecked_mut(index) = depth;
+    /// Depth tests each covered sample of a pixel, and writes the triangle's colour to those that
+    /// pass. The pixel is shaded at most once, at its centre, regardless of the number of samples,
+    /// and is left unchanged if the fragment shader discards it
+    #[inline(always)]
+    fn write_samples(
+        &self,
+        config: &RenderConfig,
+        barycentric: Vec3f,
+        coverage: u32,
+        colour_samples: &mut [RGB],
+        depth_samples: &mut [f32],
+    ) {
+        let triangle = self.triangle;
+        let depth_inv = barycentric.dot(triangle.depth_inv);
+        let mut colour = None;
+
+        for (sample, (pixel, sample_depth)) in colour_samples
+            .iter_mut()
+            .zip(depth_samples.iter_mut())
+            .enumerate()
+        {
+            if coverage & (1 << sample) == 0 {
+                continue;
+            }
+
+            // calculate depth
+            let depth = 1.0 / (depth_inv + self.depth_inv_offsets[sample]);
+
+            // check depth in
+            if depth < *sample_depth {
+                let Some(colour) =
+                    *colour.get_or_insert_with(|| self.shade(barycentric, 1.0 / depth_inv, config))
+                else {
+                    return;
+                };
+
+                if let Some((colour, write_depth)) = blend(triangle, colour, *pixel) {
+                    *pixel = colour;
+                    if write_depth {
+                        *sample_depth = depth;
                     }
                 }
+            }
+        }
+    }
 
-                index += 1;
-                point.x += 1.0;
+    /// Computes the colour of a pixel with the mesh's fragment shader if it has one, otherwise with
+    /// its shading mode tinted by its material, then applies per pixel lighting and fog. Returns
+    /// `None` if the fragment shader discards the pixel
+    #[inline(always)]
+    fn shade(&self, barycentric: Vec3f, depth: f32, config: &RenderConfig) -> Option<RGBA> {
+        let triangle = self.triangle;
+
+        let colour = match self.fragment_shader {
+            Some((fragment_shader, uniforms)) => {
+                let fragment = self.fragment(barycentric, depth, config);
+                fragment_shader.shade(&fragment, uniforms)?
+            }
+            None => {
+                shade(triangle, self.texture, barycentric, depth, config).modulate(self.base_colour)
             }
+        };
+
+        let colour = match (triangle.lighting, self.environment) {
+            (LightingMode::Pixel, _) => self.light(colour, barycentric, depth, config),
+            // vertex lighting is already in the colour, but the surroundings are still reflected
+            (LightingMode::Vertex, Some((cubemap, lod))) => {
+                let position = interpolate_vec3(&triangle.positions_depth, barycentric, depth);
+                let normal =
+                    interpolate_vec3(&triangle.normals_depth, barycentric, depth).normalise();
+                let lit = Vec3f::new(colour.r, colour.g, colour.b);
+                let reflected = self.reflect(lit, position, normal, cubemap, lod, config);
+                RGBA::new(reflected.x, reflected.y, reflected.z, colour.a)
+            }
+            (LightingMode::Vertex, None) => colour,
+        };
+
+        Some(self.fog(colour, depth, config))
+    }
 
-            index += state.width() - (tile_bounds.max_x - tile_bounds.min_x);
-            point.x = tile_points[0].x + 0.5;
-            point.y += 1.0;
+    /// Lights a shaded colour with the frame's lights, using the material's maps
+    #[inline(always)]
+    fn light(&self, colour: RGBA, barycentric: Vec3f, depth: f32, config: &RenderConfig) -> RGBA {
+        let triangle = self.triangle;
+
+        // the ambient and specular maps are sampled with the same coordinates as the diffuse map
+        let sample_map = |texture: Option<&Texture>| {
+            texture.map_or(Vec3f::uniform(1.0), |texture| {
+                let texel = sample_texture(triangle, texture, barycentric, depth, config);
+                Vec3f::new(texel.r, texel.g, texel.b)
+            })
+        };
+        let specular_map = sample_map(self.specular_texture);
+        let normal = interpolate_vec3(&triangle.normals_depth, barycentric, depth).normalise();
+
+        let surface = Surface {
+            albedo: Vec3f::new(colour.r, colour.g, colour.b),
+            ambient: sample_map(self.ambient_texture),
+            specular: Specular {
+                colour: RGB::new(
+                    self.specular.colour.r * specular_map.x,
+                    self.specular.colour.g * specular_map.y,
+                    self.specular.colour.b * specular_map.z,
+                ),
+                shininess: self.specular.shininess,
+            },
+            position: interpolate_vec3(&triangle.positions_depth, barycentric, depth),
+            normal: match self.normal_texture {
+                Some(texture) => self.map_normal(normal, texture, barycentric, depth, config),
+                None => normal,
+            },
+        };
+        let mut lit = blinn_phong(self.lights, self.shadow_map, &surface);
+
+        if let Some((cubemap, lod)) = self.environment {
+            lit = self.reflect(lit, surface.position, surface.normal, cubemap, lod, config);
         }
 
-        if DEBUG_TILES {
-            let index = (tile_bounds.min_y + tile_bounds.max_y) / 2 * state.width()
-                + (tile_bounds.min_x + tile_bounds.max_x) / 2;
-            state.pixels_mut()[index] = RGB::CYAN;
+        RGBA::new(lit.x, lit.y, lit.z, colour.a)
+    }
+
+    /// Blends a lit colour with the environment reflected about the surface's normal, weighted by
+    /// the Fresnel effect. The position and unit length normal are in view space
+    #[inline(always)]
+    fn reflect(
+        &self,
+        lit: Vec3f,
+        position: Vec3f,
+        normal: Vec3f,
+        cubemap: &Cubemap,
+        lod: f32,
+        config: &RenderConfig,
+    ) -> Vec3f {
+        // the camera is at the origin of view space
+        let view = position.normalise();
+        let cos_theta = -view.dot(normal);
+        let reflected = view + normal * (2.0 * cos_theta);
+
+        let direction = transform_direction(reflected, &self.camera_to_world);
+        let environment = cubemap.sample(direction, lod, config);
+        let fresnel = fresnel_schlick(self.reflectivity, cos_theta);
+
+        lit * (1.0 - fresnel) + Vec3f::new(environment.r, environment.g, environment.b) * fresnel
+    }
+
+    /// Blends a shaded colour towards the fog colour, by the fog at a view space depth
+    #[inline(always)]
+    fn fog(&self, colour: RGBA, depth: f32, config: &RenderConfig) -> RGBA {
+        if config.fog == Fog::Off {
+            return colour;
         }
+
+        let factor = config.fog.factor(normalise_depth(depth, config));
+        let mut fog = RGBA::from(self.fog_colour);
+        fog.a = colour.a;
+
+        fog.blend(colour, factor)
     }
 
-    fn render_partial_tile(
-        state: &mut RendererState,
-        textures: &AssetStore<Texture>,
-        triangle: &ProjectedTriangle,
-        tile_points: &[Vec2f; 4],
-        tile_bounds: &Bounds,
+    /// Perturbs the interpolated normal by the tangent space normal stored in a normal map. The
+    /// normal is left unchanged where the mesh has no tangents
+    #[inline(always)]
+    fn map_normal(
+        &self,
+        normal: Vec3f,
+        texture: &Texture,
+        barycentric: Vec3f,
+        depth: f32,
+        config: &RenderConfig,
+    ) -> Vec3f {
+        let triangle = self.triangle;
+
+        // interpolation leaves the tangent slightly off perpendicular to the normal
+        let tangent = interpolate_vec3(&triangle.tangents_depth, barycentric, depth);
+        let tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude() < f32::EPSILON {
+            return normal;
+        }
+
+        let tangent = tangent.normalise();
+        let bitangent = normal.cross(tangent) * triangle.bitangent_sign;
+
+        // each channel maps 0.0 to 1.0 onto -1.0 to 1.0
+        let texel = sample_texture(triangle, texture, barycentric, depth, config);
+        let x = texel.r * 2.0 - 1.0;
+        let y = texel.g * 2.0 - 1.0;
+        let z = texel.b * 2.0 - 1.0;
+
+        (tangent * x + bitangent * y + normal * z).normalise()
+    }
+
+    /// Interpolates the triangle's attributes at a pixel, to be passed to a fragment shader
+    #[inline(always)]
+    fn fragment(&self, barycentric: Vec3f, depth: f32, config: &'a RenderConfig) -> Fragment<'a> {
+        let triangle = self.triangle;
+
+        let (u, v) = interpolate_tex_coords(triangle, barycentric, depth);
+        Fragment {
+            varyings: Varyings::weighted_sum(&triangle.varyings_depth, barycentric).scale(depth),
+            colour: interpolate_colour(triangle, barycentric, depth),
+            tex_coord: Vec2f::new(u, v),
+            position: interpolate_vec3(&triangle.positions_depth, barycentric, depth),
+            normal: interpolate_vec3(&triangle.normals_depth, barycentric, depth).normalise(),
+            depth,
+
+            texture: self.texture,
+            lod: self
+                .texture
+                .map_or(0.0, |texture| texture_lod(triangle, texture, u, v, depth)),
+            config,
+        }
+    }
+
+    /// Draws a row four pixels at a time, without multisampling. Coverage is found exactly from
+    /// the edge functions, then depth testing and attribute interpolation are done for all four
+    /// pixels at once, with texture fetches and blending done per pixel for those that pass
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn draw_row_x4<C: CoverTest>(
+        &self,
+        config: &RenderConfig,
+        mut values: EdgeValues,
+        colour_row: &mut [RGB],
+        depth_row: &mut [f32],
     ) {
-        let texture = textures.get(triangle.texture_id.unwrap()).unwrap();
+        let triangle = self.triangle;
+        let edges = &triangle.edges;
+
+        let lane_dx = F32x4::new(0.0, 1.0, 2.0, 3.0);
+        let depth_inv = triangle.depth_inv;
+
+        for (colours, depths) in colour_row
+            .chunks_mut(F32x4::LANES)
+            .zip(depth_row.chunks_mut(F32x4::LANES))
+        {
+            // the last chunk of a row may have fewer pixels than lanes
+            let lanes = (1 << colours.len()) - 1;
+
+            let mut mask = C::coverage_x4(self, &values) & lanes;
+
+            if mask != 0 {
+                // get barycentric coordinates, exact at the first pixel
+                let base = edges.barycentric(&values);
+                let barycentric = [
+                    F32x4::splat(base.x) + lane_dx * F32x4::splat(self.barycentric_dx.x),
+                    F32x4::splat(base.y) + lane_dx * F32x4::splat(self.barycentric_dx.y),
+                    F32x4::splat(base.z) + lane_dx * F32x4::splat(self.barycentric_dx.z),
+                ];
+
+                // calculate depth
+                let depth = F32x4::splat(1.0)
+                    / (barycentric[0] * F32x4::splat(depth_inv.x)
+                        + barycentric[1] * F32x4::splat(depth_inv.y)
+                        + barycentric[2] * F32x4::splat(depth_inv.z));
 
-        let mut index = tile_bounds.min_y * state.width() + tile_bounds.min_x;
-        let mut point = tile_points[0] + 0.5;
+                // check depth in
+                mask &= depth.lt_mask(F32x4::load_partial(depths));
 
-        for y in tile_bounds.min_y..tile_bounds.max_y {
-            for x in tile_bounds.min_x..tile_bounds.max_x {
-                // get barycentric coordinates
-                let barycentric = triangle
-                    .vertices
-                    .barycentric_from_inv_area(point, triangle.two_area_inv);
-
-                // check if coordinates are in triangle (can be skipped for full cover)
-                if barycentric.x.is_sign_positive()
-                    && barycentric.y.is_sign_positive()
-                    && barycentric.z.is_sign_positive()
-                {
-                    // calculate depth
-                    let depth = 1.0 / barycentric.dot(triangle.depth_inv);
-
-                    // check depth in
-                    if depth < unsafe { *state.depth().get_unchecked(index) } {
-                        let col_a = triangle.col_depth[0] * barycentric.x;
-                        let col_b = triangle.col_depth[1] * barycentric.y;
-                        let col_c = triangle.col_depth[2] * barycentric.z;
-                        let colour = (col_a + col_b + col_c) * depth;
-
-                        let u = (triangle.tex_coords_depth[0].x * barycentric.x
-                            + triangle.tex_coords_depth[1].x * barycentric.y
-                            + triangle.tex_coords_depth[2].x * barycentric.z)
-                            * depth;
-                        let v = (triangle.tex_coords_depth[0].y * barycentric.x
-                            + triangle.tex_coords_depth[1].y * barycentric.y
-                            + triangle.tex_coords_depth[2].y * barycentric.z)
-                            * depth;
-
-                        // let normal_depth = normalise_depth(depth);
-                        // let mip_level = mip_level(normal_depth, 0.0);
-
-                        let colour = unsafe { texture.sample_unchecked(u, v, 0) };
-
-                        // SAFETY: Tile's integer bounds are within screen bounds
-                        unsafe {
-                            *state.pixels_mut().get_unchecked_mut(index) = RGB::from(colour);
-                            *state.depth_mut().get_unchecked_mut(index) = depth;
+                if mask != 0 {
+                    let colour = self.shade_x4(config, barycentric, depth, mask);
+                    let depth = depth.to_array();
+                    let colour: [RGBA; 4] =
+                        array::from_fn(|lane| self.fog(colour[lane], depth[lane], config));
+
+                    for lane in 0..colours.len() {
+                        if mask & (1 << lane) == 0 {
+                            continue;
+                        }
+
+                        let pixel = &mut colours[lane];
+                        if let Some((colour, write_depth)) = blend(triangle, colour[lane], *pixel) {
+                            *pixel = colour;
+                            if write_depth {
+                                depths[lane] = depth[lane];
+                            }
                         }
                     }
                 }
+            }
+
+            for _ in 0..colours.len() {
+                edges.step_x(&mut values);
+            }
+        }
+    }
+
+    /// Equivalent to [shade] for four pixels at once. Only lanes set in `mask` are valid
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn shade_x4(
+        &self,
+        config: &RenderConfig,
+        barycentric: [F32x4; 3],
+        depth: F32x4,
+        mask: u32,
+    ) -> [RGBA; 4] {
+        let colours = match (self.triangle.shading, self.texture) {
+            (ShadingMode::Flat(colour), _) => [RGBA::from(colour); 4],
+
+            (ShadingMode::Texture, Some(texture)) => {
+                self.sample_texture_x4(texture, barycentric, depth, mask, config)
+            }
 
-                index += 1;
-                point.x += 1.0;
+            (ShadingMode::TextureVertexColour, Some(texture)) => {
+                let texels = self.sample_texture_x4(texture, barycentric, depth, mask, config);
+                let colours = self.interpolate_colour_x4(barycentric, depth);
+                array::from_fn(|lane| texels[lane].modulate(colours[lane]))
             }
 
-            index += state.width() - (tile_bounds.max_x - tile_bounds.min_x);
-            point.x = tile_points[0].x + 0.5;
-            point.y += 1.0;
+            // Textured modes fall back to vertex colour when the mesh has no texture
+            (ShadingMode::VertexColour, _)
+            | (ShadingMode::Texture, None)
+            | (ShadingMode::TextureVertexColour, None) => {
+                self.interpolate_colour_x4(barycentric, depth)
+            }
+        };
+
+        colours.map(|colour| colour.modulate(self.base_colour))
+    }
+
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn interpolate_colour_x4(&self, barycentric: [F32x4; 3], depth: F32x4) -> [RGBA; 4] {
+        let col = &self.triangle.col_depth;
+        let channel = |c: fn(&Vec3f) -> f32| {
+            ((barycentric[0] * F32x4::splat(c(&col[0]))
+                + barycentric[1] * F32x4::splat(c(&col[1]))
+                + barycentric[2] * F32x4::splat(c(&col[2])))
+                * depth)
+                .to_array()
+        };
+
+        let r = channel(|v| v.x);
+        let g = channel(|v| v.y);
+        let b = channel(|v| v.z);
+
+        array::from_fn(|lane| RGBA::from(RGB::new(r[lane], g[lane], b[lane])))
+    }
+
+    /// Equivalent to [sample_texture] for four pixels at once. Texture coordinates and their
+    /// derivatives are found for all lanes together, while the level of detail and texel fetches
+    /// are per lane, and skipped for lanes not in `mask`
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn sample_texture_x4(
+        &self,
+        texture: &Texture,
+        barycentric: [F32x4; 3],
+        depth: F32x4,
+        mask: u32,
+        config: &RenderConfig,
+    ) -> [RGBA; 4] {
+        let triangle = self.triangle;
+        let tex = &triangle.tex_coords_depth;
+
+        let u = (barycentric[0] * F32x4::splat(tex[0].x)
+            + barycentric[1] * F32x4::splat(tex[1].x)
+            + barycentric[2] * F32x4::splat(tex[2].x))
+            * depth;
+        let v = (barycentric[0] * F32x4::splat(tex[0].y)
+            + barycentric[1] * F32x4::splat(tex[1].y)
+            + barycentric[2] * F32x4::splat(tex[2].y))
+            * depth;
+
+        // Quotient rule, as in `texture_lod`
+        let dx = triangle.tex_coords_depth_dx;
+        let dy = triangle.tex_coords_depth_dy;
+        let du_dx = ((F32x4::splat(dx.x) - u * F32x4::splat(dx.z)) * depth).to_array();
+        let dv_dx = ((F32x4::splat(dx.y) - v * F32x4::splat(dx.z)) * depth).to_array();
+        let du_dy = ((F32x4::splat(dy.x) - u * F32x4::splat(dy.z)) * depth).to_array();
+        let dv_dy = ((F32x4::splat(dy.y) - v * F32x4::splat(dy.z)) * depth).to_array();
+
+        let u = u.to_array();
+        let v = v.to_array();
+
+        array::from_fn(|lane| {
+            if mask & (1 << lane) == 0 {
+                return RGBA::default();
+            }
+
+            let lod = texture.lod(
+                Vec2f::new(du_dx[lane], dv_dx[lane]),
+                Vec2f::new(du_dy[lane], dv_dy[lane]),
+            );
+
+            unsafe { texture.sample_lod_unchecked(u[lane], v[lane], lod, config) }
+        })
+    }
+}
+
+/// Computes the colour of a pixel within the triangle, from its barycentric coordinates and depth,
+/// according to the triangle's shading mode
+#[inline(always)]
+fn shade(
+    triangle: &ProjectedTriangle,
+    texture: Option<&Texture>,
+    barycentric: Vec3f,
+    depth: f32,
+    config: &RenderConfig,
+) -> RGBA {
+    match (triangle.shading, texture) {
+        (ShadingMode::Flat(colour), _) => RGBA::from(colour),
+
+        (ShadingMode::Texture, Some(texture)) => {
+            sample_texture(triangle, texture, barycentric, depth, config)
         }
 
-        if DEBUG_TILES {
-            let index = (tile_bounds.min_y + tile_bounds.max_y) / 2 * state.width()
-                + (tile_bounds.min_x + tile_bounds.max_x) / 2;
-            state.pixels_mut()[index] = RGB::MAGENTA;
+        (ShadingMode::TextureVertexColour, Some(texture)) => {
+            let colour = sample_texture(triangle, texture, barycentric, depth, config);
+            colour.modulate(RGBA::from(interpolate_colour(triangle, barycentric, depth)))
         }
+
+        // Textured modes fall back to vertex colour when the mesh has no texture
+        (ShadingMode::VertexColour, _)
+        | (ShadingMode::Texture, None)
+        | (ShadingMode::TextureVertexColour, None) => {
+            RGBA::from(interpolate_colour(triangle, barycentric, depth))
+        }
+    }
+}
+
+/// Combines the shaded colour with the colour already in the framebuffer, according to the
+/// triangle's alpha mode. Returns `None` if the pixel is discarded, otherwise the final colour and
+/// whether depth should be written
+#[inline(always)]
+fn blend(triangle: &ProjectedTriangle, colour: RGBA, dst: RGB) -> Option<(RGB, bool)> {
+    match triangle.alpha_mode {
+        AlphaMode::Opaque => Some((RGB::from(colour), true)),
+        AlphaMode::Cutout(threshold) => (colour.a >= threshold).then(|| (RGB::from(colour), true)),
+        AlphaMode::Blend => Some((RGB::from(colour).blend(dst, colour.a), false)),
     }
 }
 
+/// Samples the texture at the perspective correct texture coordinates, using the level of detail
+/// that best matches the rate of change of the texture coordinates at this pixel
+#[inline(always)]
+fn sample_texture(
+    triangle: &ProjectedTriangle,
+    texture: &Texture,
+    barycentric: Vec3f,
+    depth: f32,
+    config: &RenderConfig,
+) -> RGBA {
+    let (u, v) = interpolate_tex_coords(triangle, barycentric, depth);
+    let lod = texture_lod(triangle, texture, u, v, depth);
+
+    unsafe { texture.sample_lod_unchecked(u, v, lod, config) }
+}
+
+/// Calculates the level of detail at a pixel from the screen space derivatives of its perspective
+/// correct texture coordinates
+#[inline(always)]
+fn texture_lod(triangle: &ProjectedTriangle, texture: &Texture, u: f32, v: f32, depth: f32) -> f32 {
+    // Differentiate the perspective divide (u = (u/z) / (1/z)) using the quotient rule, where the
+    // numerator and denominator have constant screen space derivatives
+    let dx = triangle.tex_coords_depth_dx;
+    let dy = triangle.tex_coords_depth_dy;
+    let tex_coord_dx = Vec2f::new(dx.x - u * dx.z, dx.y - v * dx.z) * depth;
+    let tex_coord_dy = Vec2f::new(dy.x - u * dy.z, dy.y - v * dy.z) * depth;
+
+    texture.lod(tex_coord_dx, tex_coord_dy)
+}
+
+#[inline(always)]
+fn interpolate_colour(triangle: &ProjectedTriangle, barycentric: Vec3f, depth: f32) -> RGB {
+    let col_a = triangle.col_depth[0] * barycentric.x;
+    let col_b = triangle.col_depth[1] * barycentric.y;
+    let col_c = triangle.col_depth[2] * barycentric.z;
+
+    RGB::from((col_a + col_b + col_c) * depth)
+}
+
+/// Perspective correct interpolation of a vector attribute that has been divided by depth
+#[inline(always)]
+fn interpolate_vec3(values: &[Vec3f; 3], barycentric: Vec3f, depth: f32) -> Vec3f {
+    (values[0] * barycentric.x + values[1] * barycentric.y + values[2] * barycentric.z) * depth
+}
+
+#[inline(always)]
+fn interpolate_tex_coords(
+    triangle: &ProjectedTriangle,
+    barycentric: Vec3f,
+    depth: f32,
+) -> (f32, f32) {
+    let u = (triangle.tex_coords_depth[0].x * barycentric.x
+        + triangle.tex_coords_depth[1].x * barycentric.y
+        + triangle.tex_coords_depth[2].x * barycentric.z)
+        * depth;
+    let v = (triangle.tex_coords_depth[0].y * barycentric.x
+        + triangle.tex_coords_depth[1].y * barycentric.y
+        + triangle.tex_coords_depth[2].y * barycentric.z)
+        * depth;
+
+    (u, v)
+}
+
 #[cfg(feature = "multithreaded")]
 mod multithreading {
     use std::{
-        cell::UnsafeCell,
-        sync::{
-            atomic::{AtomicIsize, Ordering},
-            Arc,
-        },
+        cmp::Reverse,
+        sync::{Arc, Mutex},
     };
 
-    use maths::linear::Vec2f;
-    use rayon::{ThreadPool, ThreadPoolBuilder};
+    use rayon::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+    use super::{Band, FrameSetup};
+
+    /// Clones share the same pool
+    #[derive(Clone)]
+    pub struct ThreadedRenderer {
+        workers: Arc<ThreadPool>,
+    }
+
+    impl ThreadedRenderer {
+        /// Creates a pool with the given number of threads, where 0 lets the pool decide. Returns
+        /// `None` for a single thread, as everything can run on the calling thread instead
+        pub fn new(num_threads: usize) -> Result<Option<Self>, ThreadPoolBuildError> {
+            if num_threads == 1 {
+                return Ok(None);
+            }
+
+            let workers = ThreadPoolBuilder::new().num_threads(num_threads).build()?;
+
+            Ok(Some(Self {
+                workers: Arc::new(workers),
+            }))
+        }
+
+        pub fn thread_pool(&self) -> &ThreadPool {
+            &self.workers
+        }
+
+        /// Renders each band on whichever thread picks it up. Bands are handed out in order of the
+        /// number of triangles binned to them, so the most expensive are started first rather than
+        /// holding up the end of the frame
+        pub fn render<'a>(&self, frame: &FrameSetup, bands: impl Iterator<Item = Band<'a>>) {
+            let mut bands: Vec<_> = bands.filter(|band| band.cost() > 0).collect();
+            bands.sort_by_cached_key(|band| Reverse(band.cost()));
+            let bands = Mutex::new(bands.into_iter());
+
+            self.workers.broadcast(|_| loop {
+                let Some(band) = bands.lock().unwrap().next() else {
+                    return;
+                };
+
+                band.render(frame);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maths::{
+        geometry::Triangle,
+        linear::{Mat4f, Vec2f, Vec3f, Vec4f},
+    };
 
     use crate::{
-        asset_manager::AssetStore,
-        colour::RGB,
+        asset_manager::AssetId,
+        colour::{RGB, RGBA},
+        config::{Msaa, RenderConfig},
         framebuffer::Framebuffer,
-        model::ProjectedTriangle,
+        light::Lighting,
+        material::Material,
+        model::{AlphaMode, Mesh, ProjectedTriangle, ShadingMode, Vertex},
+        raster::EdgeFunctions,
+        renderer::Renderer,
+        shader::{
+            Fragment, FragmentShader, Shader, Uniforms, VertexInput, VertexOutput, VertexShader,
+        },
+        shapes::{unit_cube_mesh, unit_quad_mesh, unit_sphere_mesh},
+        testing::{
+            assert_near, centre, pixel, render, scene, test_config, CLEAR_COLOUR, HEIGHT, WIDTH,
+        },
         texture::Texture,
-        util::{mip_level, normalise_depth},
-        THREADS,
     };
 
-    use super::{Bounds, Cover, Tile};
+    #[cfg(feature = "multithreaded")]
+    use super::MIN_BIN_CHUNK;
+    use super::{Cover, TileRenderer};
+
+    /// Renders a single frame of a flat shaded cube at the given position
+    fn render_cube(x: f32, y: f32, z: f32) -> Vec<RGB> {
+        let (mut renderer, cube) = scene(test_config(), unit_cube_mesh(), Vec3f::new(x, y, z));
+        renderer
+            .assets_mut()
+            .set_mesh_shading(cube, ShadingMode::Flat(RGB::RED));
 
-    struct SharedState<'a> {
-        textures: &'a AssetStore<Texture>,
-        colour_buffer: &'a [UnsafeCell<RGB>],
-        depth_buffer: &'a [UnsafeCell<f32>],
-        tiles: &'a [UnsafeCell<Tile>],
+        render(&mut renderer)
     }
 
-    unsafe impl Sync for SharedState<'_> {}
-    unsafe impl Send for SharedState<'_> {}
+    /// A quad with vertex colours given by [quad_colour]
+    fn coloured_quad_mesh() -> Mesh {
+        let mut mesh = unit_quad_mesh();
+        for vertex in mesh.vertices.iter_mut() {
+            let colour = quad_colour(vertex.position.x, vertex.position.y);
+            vertex.colour = Vec3f::new(colour.r, colour.g, colour.b);
+        }
+        mesh
+    }
 
-    pub struct ThreadedRenderer {
-        workers: ThreadPool,
-        tiles_available: Arc<AtomicIsize>,
+    /// Renders a single frame of a quad facing the camera, which spans about 10 pixels either side
+    /// of the centre of the screen. The quad's vertex colours are given by [quad_colour], and
+    /// `setup` is called with its mesh before rendering
+    fn render_quad(
+        config: RenderConfig,
+        setup: impl FnOnce(&mut Renderer, AssetId<Mesh>),
+    ) -> Vec<RGB> {
+        let (mut renderer, quad) = scene(config, coloured_quad_mesh(), Vec3f::new(0.0, 0.0, 3.0));
+        setup(&mut renderer, quad);
+
+        render(&mut renderer)
     }
 
-    impl Default for ThreadedRenderer {
-        fn default() -> Self {
-            Self::new(THREADS)
+    /// The vertex colour of the quad drawn by [render_quad] at a position on the quad, where red
+    /// increases from left to right, and green from bottom to top
+    fn quad_colour(x: f32, y: f32) -> RGB {
+        RGB::new(x * 0.5 + 0.5, y * 0.5 + 0.5, 0.5)
+    }
+
+    /// A triangle given directly in screen space, with the same depth at every vertex
+    fn screen_triangle(points: [(f32, f32); 3], depth: f32) -> ProjectedTriangle {
+        let vertices = Triangle::from(points.map(|(x, y)| Vec2f::new(x, y)));
+
+        ProjectedTriangle {
+            vertices,
+            depth_inv: Vec3f::uniform(1.0 / depth),
+            edges: EdgeFunctions::new(&vertices),
+            sat_edges: [
+                (vertices.b - vertices.a).perpendicular(),
+                (vertices.c - vertices.b).perpendicular(),
+                (vertices.a - vertices.c).perpendicular(),
+            ],
+            ..Default::default()
         }
     }
 
-    impl ThreadedRenderer {
-        pub fn new(num_threads: usize) -> Self {
-            let workers = ThreadPoolBuilder::new()
-                .num_threads(num_threads)
-                .build()
-                .unwrap();
+    /// The indices of the triangles binned to each tile
+    fn binned(renderer: &TileRenderer) -> Vec<Vec<u32>> {
+        renderer
+            .tiles
+            .iter()
+            .map(|tile| tile.triangles.iter().map(|&(_, index)| index).collect())
+            .collect()
+    }
+
+    /// Writes the height of each vertex in world space to the first varying
+    struct HeightVertexShader;
+
+    impl VertexShader for HeightVertexShader {
+        fn shade(&self, vertex: &VertexInput, _uniforms: &Uniforms) -> VertexOutput {
+            let mut output = VertexOutput {
+                position: vertex.position,
+                normal: vertex.normal,
+                varyings: Default::default(),
+            };
+            output.varyings[0] = vertex.position.y;
+            output
+        }
+    }
+
+    /// Colours each pixel by the first varying, discarding those where it's negative
+    struct HeightFragmentShader;
+
+    impl FragmentShader for HeightFragmentShader {
+        fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Option<RGBA> {
+            let height = fragment.varyings[0];
+            (height >= 0.0).then(|| RGBA::from(RGB::new(height, 0.0, 0.0)))
+        }
+    }
+
+    fn height_shader() -> Shader {
+        Shader::new(
+            "height".into(),
+            Some(Box::new(HeightVertexShader)),
+            Some(Box::new(HeightFragmentShader)),
+        )
+    }
+
+    /// A renderer with overlapping opaque and blended meshes spawned in it
+    #[cfg(feature = "multithreaded")]
+    fn blended_scene(config: RenderConfig) -> Renderer {
+        let (mut renderer, _) = scene(config, unit_sphere_mesh(8), Vec3f::new(0.3, 0.1, 3.0));
+
+        let cube = renderer.assets_mut().insert_mesh(unit_cube_mesh());
+        renderer
+            .assets_mut()
+            .set_mesh_alpha_mode(cube, AlphaMode::Blend);
+        renderer
+            .assets_mut()
+            .spawn_mesh_instance(cube, &Mat4f::translate(-0.6, -0.2, 2.5));
+        renderer
+            .assets_mut()
+            .spawn_mesh_instance(cube, &Mat4f::translate(0.9, 0.0, 0.3));
+
+        renderer
+    }
+
+    /// The exact bits of each pixel, for comparing renders which should be identical
+    #[cfg(feature = "multithreaded")]
+    fn to_bits(pixels: &[RGB]) -> Vec<[u32; 3]> {
+        pixels
+            .iter()
+            .map(|pixel| [pixel.r.to_bits(), pixel.g.to_bits(), pixel.b.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    fn cube_in_front_of_camera() {
+        let pixels = render_cube(0.0, 0.0, 3.0);
 
-            let tiles_available = Arc::new(AtomicIsize::new(isize::MIN));
+        assert_eq!(centre(&pixels), RGB::RED);
+        assert_eq!(pixel(&pixels, 0, 0), CLEAR_COLOUR);
+        assert_eq!(pixel(&pixels, WIDTH - 1, HEIGHT - 1), CLEAR_COLOUR);
+    }
+
+    #[test]
+    fn cube_crossing_near_plane_and_screen_edge() {
+        // the face nearest the camera passes through the near plane, and reaches far beyond the
+        // right edge of the screen
+        let pixels = render_cube(0.9, 0.0, 0.3);
+
+        assert_eq!(pixel(&pixels, WIDTH - 1, HEIGHT / 2), RGB::RED);
+        assert_eq!(pixel(&pixels, WIDTH * 7 / 8, HEIGHT / 2), RGB::RED);
+        assert_eq!(pixel(&pixels, WIDTH / 8, HEIGHT / 2), CLEAR_COLOUR);
+    }
+
+    #[test]
+    fn cube_behind_camera() {
+        let pixels = render_cube(0.0, 0.0, -3.0);
+
+        assert!(pixels.iter().all(|&pixel| pixel == CLEAR_COLOUR));
+    }
+
+    /// Shades like [ShadingMode::TextureVertexColour], which draws each pixel on its own rather
+    /// than four at a time
+    struct TextureVertexColourShader;
+
+    impl FragmentShader for TextureVertexColourShader {
+        fn shade(&self, fragment: &Fragment, _uniforms: &Uniforms) -> Option<RGBA> {
+            let colour = RGBA::from(fragment.colour);
+            Some(
+                fragment
+                    .sample_texture(fragment.tex_coord)
+                    .map_or(colour, |texel| texel.modulate(colour)),
+            )
+        }
+    }
+
+    #[test]
+    fn simd_rows_match_scalar_rows() {
+        // without the `simd` feature, both renders draw one pixel at a time
+        let render_with = |scalar: bool| {
+            let (mut renderer, sphere) = scene(
+                test_config(),
+                unit_sphere_mesh(8),
+                Vec3f::new(0.3, 0.1, 3.0),
+            );
+            renderer.set_lighting(Some(Lighting::new(
+                Vec3f::new(-1.0, -1.0, 1.0),
+                RGB::new(0.5, 0.5, 0.5),
+                RGB::new(0.25, 0.25, 0.25),
+            )));
+
+            let checker = (0..16)
+                .map(|i| match (i % 4 + i / 4) % 3 {
+                    0 => RGBA::from(RGB::RED),
+                    1 => RGBA::from(RGB::new(0.25, 1.0, 0.5)),
+                    _ => RGBA::from(RGB::WHITE),
+                })
+                .collect();
+            let texture = Texture::from_pixels("checker".into(), 4, 4, checker);
+            let mut material = Material::new("material".into());
+            material.diffuse_texture = Some(renderer.assets_mut().insert_texture(texture));
+            let material = renderer.assets_mut().insert_material(material);
+            let shader = scalar.then(|| {
+                let shader = Shader::new(
+                    "texture vertex colour".into(),
+                    None,
+                    Some(Box::new(TextureVertexColourShader)),
+                );
+                renderer.assets_mut().insert_shader(shader)
+            });
 
-            Self {
-                workers,
-                tiles_available,
+            let cube = renderer.assets_mut().insert_mesh(unit_cube_mesh());
+            for mesh in [sphere, cube] {
+                let assets = renderer.assets_mut();
+                assets.set_mesh_material(mesh, Some(material));
+                assets.set_mesh_shading(mesh, ShadingMode::TextureVertexColour);
+                assets.set_mesh_shader(mesh, shader);
             }
+            renderer
+                .assets_mut()
+                .set_mesh_alpha_mode(cube, AlphaMode::Blend);
+            renderer
+                .assets_mut()
+                .spawn_mesh_instance(cube, &Mat4f::translate(-0.6, -0.2, 2.5));
+
+            render(&mut renderer)
+        };
+
+        let simd = render_with(false);
+        let scalar = render_with(true);
+        assert!(simd.iter().any(|&pixel| pixel != CLEAR_COLOUR));
+
+        // barycentric coordinates are stepped across the row rather than computed per pixel, so
+        // may differ by a little rounding
+        for (&simd, &scalar) in simd.iter().zip(scalar.iter()) {
+            assert_near(simd, scalar, 1e-4);
         }
+    }
+
+    #[test]
+    fn shading_modes() {
+        let texel = RGB::new(0.5, 1.0, 0.25);
 
-        pub fn render(
-            &self,
-            framebuffer: &mut Framebuffer,
-            textures: &AssetStore<Texture>,
-            tiles: &mut [Tile],
-        ) {
-            self.tiles_available
-                .store(tiles.len().try_into().unwrap(), Ordering::Release);
-            let shared_state = unsafe {
-                Arc::new(SharedState {
-                    textures,
-                    colour_buffer: core::mem::transmute(framebuffer.pixels()),
-                    depth_buffer: core::mem::transmute(framebuffer.depth()),
-                    tiles: core::mem::transmute(tiles),
+        for threads in [1, 4] {
+            let render_with = |shading: ShadingMode, textured: bool| {
+                let config = RenderConfig {
+                    threads,
+                    ..test_config()
+                };
+                render_quad(config, |renderer, quad| {
+                    let mut material = Material::new("material".into());
+                    material.diffuse_texture = textured.then(|| {
+                        let texture = Texture::from_pixels(
+                            "texture".into(),
+                            2,
+                            2,
+                            vec![RGBA::from(texel); 4],
+                        );
+                        renderer.assets_mut().insert_texture(texture)
+                    });
+                    let material = renderer.assets_mut().insert_material(material);
+                    renderer
+                        .assets_mut()
+                        .set_mesh_material(quad, Some(material));
+                    renderer.assets_mut().set_mesh_shading(quad, shading);
                 })
             };
+            // a pixel 8 pixels left of the centre, which is 0.7 of the way to the left edge of the
+            // quad
+            let left = |pixels: &[RGB]| pixel(pixels, WIDTH / 2 - 8, HEIGHT / 2);
+
+            let pixels = render_with(ShadingMode::Flat(RGB::RED), true);
+            assert_eq!(centre(&pixels), RGB::RED);
+            assert_eq!(left(&pixels), RGB::RED);
+
+            for (shading, textured) in [
+                (ShadingMode::VertexColour, true),
+                (ShadingMode::Texture, false),
+                (ShadingMode::TextureVertexColour, false),
+            ] {
+                let pixels = render_with(shading, textured);
+                assert_near(centre(&pixels), quad_colour(0.0, 0.0), 0.05);
+                assert_near(left(&pixels), quad_colour(-0.7, 0.0), 0.05);
+            }
 
-            self.workers.scope(|s| {
-                s.spawn_broadcast(|s, b| loop {
-                    let Ok(tiles_available) = self.tiles_available.fetch_update(
-                        Ordering::Release,
-                        Ordering::Acquire,
-                        |x| {
-                            if x < 1 {
-                                Some(isize::MIN)
-                            } else {
-                                Some(x - 32)
-                            }
-                        },
-                    ) else {
-                        return;
-                    };
-
-                    if tiles_available == isize::MIN {
-                        return;
-                    };
-
-                    let from = (tiles_available - 32).max(0);
-                    let to = tiles_available;
-
-                    for i in from..to {
-                        let tile =
-                            unsafe { &mut *shared_state.tiles.get(i as usize).unwrap().get() };
-
-                        for (cover, triangle) in tile.triangles.drain(..) {
-                            let triangle = unsafe { &*triangle };
-
-                            match cover {
-                                Cover::Full => {
-                                    Self::render_full_tile(
-                                        shared_state.colour_buffer,
-                                        shared_state.depth_buffer,
-                                        shared_state.textures,
-                                        triangle,
-                                        &tile.points,
-                                        &tile.bounds,
-                                        framebuffer.width(),
-                                    );
-                                }
-                                Cover::Partial => {
-                                    Self::render_partial_tile(
-                                        shared_state.colour_buffer,
-                                        shared_state.depth_buffer,
-                                        shared_state.textures,
-                                        triangle,
-                                        &tile.points,
-                                        &tile.bounds,
-                                        framebuffer.width(),
-                                    );
-                                }
-                            }
-                        }
-                    }
-                });
-            });
-        }
+            let pixels = render_with(ShadingMode::Texture, true);
+            assert_eq!(centre(&pixels), texel);
+            assert_eq!(left(&pixels), texel);
 
-        fn render_partial_tile(
-            colour_buffer: &[UnsafeCell<RGB>],
-            depth_buffer: &[UnsafeCell<f32>],
-            textures: &AssetStore<Texture>,
-            triangle: &ProjectedTriangle,
-            tile_points: &[Vec2f; 4],
-            tile_bounds: &Bounds,
-            width: usize,
-        ) {
-            let texture = textures.get(triangle.texture_id.unwrap()).unwrap();
-
-            let mut index = tile_bounds.min_y * width + tile_bounds.min_x;
-            let mut point = tile_points[0] + 0.5;
-
-            for y in tile_bounds.min_y..tile_bounds.max_y {
-                for x in tile_bounds.min_x..tile_bounds.max_x {
-                    // get barycentric coordinates
-                    let barycentric = triangle
-                        .vertices
-                        .barycentric_from_inv_area(point, triangle.two_area_inv);
-
-                    // check if coordinates are in triangle (can be skipped for full cover)
-                    if barycentric.x.is_sign_positive()
-                        && barycentric.y.is_sign_positive()
-                        && barycentric.z.is_sign_positive()
-                    {
-                        // calculate depth
-                        let depth = 1.0 / barycentric.dot(triangle.depth_inv);
-
-                        // check depth in
-                        if depth < unsafe { *depth_buffer.get_unchecked(index).get() } {
-                            let u = (triangle.tex_coords_depth[0].x * barycentric.x
-                                + triangle.tex_coords_depth[1].x * barycentric.y
-                                + triangle.tex_coords_depth[2].x * barycentric.z)
-                                * depth;
-                            let v = (triangle.tex_coords_depth[0].y * barycentric.x
-                                + triangle.tex_coords_depth[1].y * barycentric.y
-                                + triangle.tex_coords_depth[2].y * barycentric.z)
-                                * depth;
-
-                            // let normal_depth = normalise_depth(depth);
-                            // let mip_level = mip_level(normal_depth, 0.0);
-                            let colour = unsafe { texture.sample_unchecked(u, v, 0) };
-
-                            // SAFETY: Tile's integer bounds are within screen bounds
-                            unsafe {
-                                *colour_buffer.get_unchecked(index).get() = RGB::from(colour);
-                                *depth_buffer.get_unchecked(index).get() = depth;
-                            }
-                        }
-                    }
+            let pixels = render_with(ShadingMode::TextureVertexColour, true);
+            let modulated = |colour: RGB| RGB::new(colour.r * 0.5, colour.g, colour.b * 0.25);
+            assert_near(centre(&pixels), modulated(quad_colour(0.0, 0.0)), 0.05);
+            assert_near(left(&pixels), modulated(quad_colour(-0.7, 0.0)), 0.05);
+        }
+    }
 
-                    index += 1;
-                    point.x += 1.0;
+    #[test]
+    fn mip_level_from_screen_size() {
+        // the quad is about 21 pixels across, so a 64x64 texture has 3 texels per pixel, at a
+        // level of detail of about 1.6
+        let render_with = |lod_bias: f32| {
+            let pixels = render_quad(test_config(), |renderer, quad| {
+                let mut texture =
+                    Texture::from_pixels("levels".into(), 64, 64, vec![RGBA::WHITE; 64 * 64]);
+                for (i, level) in texture.levels.clone().iter().enumerate() {
+                    let len = level.width * level.height;
+                    let c = i as f32 / 8.0;
+                    texture.pixels[level.offset..level.offset + len].fill(RGBA::new(c, c, c, 1.0));
                 }
+                texture.lod_bias = lod_bias;
+
+                let mut material = Material::new("material".into());
+                material.diffuse_texture = Some(renderer.assets_mut().insert_texture(texture));
+                let material = renderer.assets_mut().insert_material(material);
+                renderer
+                    .assets_mut()
+                    .set_mesh_material(quad, Some(material));
+            });
+            centre(&pixels).r * 8.0
+        };
+
+        assert_eq!(render_with(0.0), 2.0);
+        assert_eq!(render_with(-1.0), 1.0);
+        assert_eq!(render_with(-2.0), 0.0);
+        assert_eq!(render_with(1.0), 3.0);
+    }
+
+    #[test]
+    fn alpha_cutout_thresholds() {
+        // the left half of the quad has an alpha of 0.25 and the right half 0.75, in front of a
+        // green quad
+        let texels = [
+            RGBA::new(1.0, 0.0, 0.0, 0.25),
+            RGBA::new(0.0, 0.0, 1.0, 0.75),
+        ];
+        let render_with = |alpha_mode: AlphaMode| {
+            let pixels = render_quad(test_config(), |renderer, quad| {
+                let texture = Texture::from_pixels("alpha".into(), 2, 1, texels.to_vec());
+                let mut material = Material::new("material".into());
+                material.diffuse_texture = Some(renderer.assets_mut().insert_texture(texture));
+                let material = renderer.assets_mut().insert_material(material);
+                renderer
+                    .assets_mut()
+                    .set_mesh_material(quad, Some(material));
+                renderer.assets_mut().set_mesh_alpha_mode(quad, alpha_mode);
+
+                let background = renderer.assets_mut().insert_mesh(unit_quad_mesh());
+                renderer
+                    .assets_mut()
+                    .set_mesh_shading(background, ShadingMode::Flat(RGB::GREEN));
+                renderer
+                    .assets_mut()
+                    .spawn_mesh_instance(background, &Mat4f::translate(0.0, 0.0, 4.0));
+            });
 
-                index += width - (tile_bounds.max_x - tile_bounds.min_x);
-                point.x = tile_points[0].x + 0.5;
-                point.y += 1.0;
+            (
+                pixel(&pixels, WIDTH / 2 - 4, HEIGHT / 2),
+                pixel(&pixels, WIDTH / 2 + 4, HEIGHT / 2),
+            )
+        };
+
+        assert_eq!(render_with(AlphaMode::Opaque), (RGB::RED, RGB::BLUE));
+        assert_eq!(render_with(AlphaMode::Cutout(0.2)), (RGB::RED, RGB::BLUE));
+        assert_eq!(render_with(AlphaMode::Cutout(0.5)), (RGB::GREEN, RGB::BLUE));
+        // alpha equal to the threshold is kept
+        assert_eq!(
+            render_with(AlphaMode::Cutout(0.75)),
+            (RGB::GREEN, RGB::BLUE)
+        );
+        assert_eq!(
+            render_with(AlphaMode::Cutout(0.8)),
+            (RGB::GREEN, RGB::GREEN)
+        );
+    }
+
+    #[test]
+    fn blended_meshes_are_drawn_back_to_front() {
+        // an opaque quad in front hides blended quads behind it, even though they're drawn later
+        let (mut renderer, front) =
+            scene(test_config(), unit_quad_mesh(), Vec3f::new(-1.0, 0.0, 3.5));
+        renderer
+            .assets_mut()
+            .set_mesh_shading(front, ShadingMode::Flat(RGB::BLACK));
+
+        let mut material = Material::new("half opacity".into());
+        material.opacity = 0.5;
+        let material = renderer.assets_mut().insert_material(material);
+
+        // spawned from front to back, with an opaque quad furthest away
+        let layers = [
+            (RGB::WHITE, 6.0, AlphaMode::Opaque),
+            (RGB::BLUE, 3.0, AlphaMode::Blend),
+            (RGB::GREEN, 4.0, AlphaMode::Blend),
+            (RGB::RED, 5.0, AlphaMode::Blend),
+        ];
+        for (colour, z, alpha_mode) in layers {
+            let quad = renderer.assets_mut().insert_mesh(unit_quad_mesh());
+            if alpha_mode == AlphaMode::Blend {
+                renderer
+                    .assets_mut()
+                    .set_mesh_material(quad, Some(material));
             }
+            renderer
+                .assets_mut()
+                .set_mesh_shading(quad, ShadingMode::Flat(colour));
+            renderer.assets_mut().set_mesh_alpha_mode(quad, alpha_mode);
+            renderer
+                .assets_mut()
+                .spawn_mesh_instance(quad, &Mat4f::translate(0.0, 0.0, z));
         }
 
-        fn render_full_tile(
-            colour_buffer: &[UnsafeCell<RGB>],
-            depth_buffer: &[UnsafeCell<f32>],
-            textures: &AssetStore<Texture>,
-            triangle: &ProjectedTriangle,
-            tile_points: &[Vec2f; 4],
-            tile_bounds: &Bounds,
-            width: usize,
-        ) {
-            let texture = textures.get(triangle.texture_id.unwrap()).unwrap();
-
-            let mut index = tile_bounds.min_y * width + tile_bounds.min_x;
-            let mut point = tile_points[0] + 0.5;
-
-            for y in tile_bounds.min_y..tile_bounds.max_y {
-                for x in tile_bounds.min_x..tile_bounds.max_x {
-                    // get barycentric coordinates
-                    let barycentric = triangle
-                        .vertices
-                        .barycentric_from_inv_area(point, triangle.two_area_inv);
-
-                    // calculate depth
-                    let depth = 1.0 / barycentric.dot(triangle.depth_inv);
-
-                    // check depth in
-                    if depth < unsafe { *depth_buffer.get_unchecked(index).get() } {
-                        let u = (triangle.tex_coords_depth[0].x * barycentric.x
-                            + triangle.tex_coords_depth[1].x * barycentric.y
-                            + triangle.tex_coords_depth[2].x * barycentric.z)
-                            * depth;
-                        let v = (triangle.tex_coords_depth[0].y * barycentric.x
-                            + triangle.tex_coords_depth[1].y * barycentric.y
-                            + triangle.tex_coords_depth[2].y * barycentric.z)
-                            * depth;
-
-                        // let normal_depth = normalise_depth(depth);
-                        // let mip_level = mip_level(normal_depth, 0.0);
-                        let colour = unsafe { texture.sample_unchecked(u, v, 0) };
-
-                        // SAFETY: Tile's integer bounds are within screen bounds
-                        unsafe {
-                            *colour_buffer.get_unchecked(index).get() = RGB::from(colour);
-                            *depth_buffer.get_unchecked(index).get() = depth;
-                        }
-                    }
+        let pixels = render(&mut renderer);
+
+        let expected = [RGB::RED, RGB::GREEN, RGB::BLUE]
+            .iter()
+            .fold(RGB::WHITE, |dst, colour| colour.blend(dst, 0.5));
+        assert_near(centre(&pixels), expected, 1e-5);
+
+        // only the nearest blended quad is in front of the opaque one
+        let behind = pixel(&pixels, WIDTH / 2 - 6, HEIGHT / 2);
+        assert_eq!(behind, RGB::BLUE.blend(RGB::BLACK, 0.5));
+    }
 
-                    index += 1;
-                    point.x += 1.0;
+    #[test]
+    fn msaa_resolves_partially_covered_pixels() {
+        // a single triangle, with edges at several angles
+        let render_with = |msaa: Msaa, shading: ShadingMode| {
+            let vertices = [
+                Vec3f::new(1.2, -1.0, 0.0),
+                Vec3f::new(-1.0, -0.8, 0.0),
+                Vec3f::new(0.3, 1.0, 0.0),
+            ]
+            .map(|position| {
+                let colour = quad_colour(position.x, position.y);
+                Vertex {
+                    position,
+                    normal: Vec3f::new(0.0, 0.0, -1.0),
+                    colour: Vec3f::new(colour.r, colour.g, colour.b),
+                    tex_coord: Vec2f::new(0.0, 0.0),
+                    tangent: Vec4f::default(),
+                }
+            });
+            let mesh = Mesh::new("triangle".into(), vertices.to_vec(), vec![0, 1, 2]);
+            let config = RenderConfig {
+                msaa,
+                ..test_config()
+            };
+            let (mut renderer, triangle) = scene(config, mesh, Vec3f::new(0.0, 0.0, 3.0));
+            renderer.assets_mut().set_mesh_shading(triangle, shading);
+
+            render(&mut renderer)
+        };
+        let white = ShadingMode::Flat(RGB::WHITE);
+
+        let aliased = render_with(Msaa::Off, white);
+        assert!(aliased
+            .iter()
+            .all(|&pixel| pixel == RGB::WHITE || pixel == CLEAR_COLOUR));
+        let aliased_shaded = render_with(Msaa::Off, ShadingMode::VertexColour);
+
+        for msaa in [Msaa::X2, Msaa::X4, Msaa::X8] {
+            let samples = msaa.samples() as f32;
+            let coverage = render_with(msaa, white);
+            let shaded = render_with(msaa, ShadingMode::VertexColour);
+
+            // white over black resolves to the fraction of samples covered
+            let mut partial = 0;
+            let mut full = 0;
+            for (i, &pixel) in coverage.iter().enumerate() {
+                let covered = pixel.r * samples;
+                assert!(
+                    covered.fract() == 0.0 && pixel.g == pixel.r && pixel.b == pixel.r,
+                    "{msaa:?}: {pixel:?}"
+                );
+
+                if pixel == RGB::WHITE {
+                    // shaded once at the pixel centre, as without multisampling. The four pixel
+                    // path used without multisampling may round differently in the last bit
+                    assert_near(shaded[i], aliased_shaded[i], 1e-5);
+                    full += 1;
+                } else if pixel != CLEAR_COLOUR {
+                    partial += 1;
                 }
+            }
 
-                index += width - (tile_bounds.max_x - tile_bounds.min_x);
-                point.x = tile_points[0].x + 0.5;
-                point.y += 1.0;
+            assert!(full > 100, "{msaa:?}: {full} fully covered pixels");
+            assert!(partial > 20, "{msaa:?}: {partial} partially covered pixels");
+        }
+    }
+
+    #[test]
+    fn occluded_triangles_are_rejected() {
+        let config = RenderConfig {
+            tile_width: 8,
+            tile_height: 8,
+            ..test_config()
+        };
+        let mut renderer = TileRenderer::unthreaded(&config);
+        renderer.update_viewport(16, 16, &config);
+        let mut framebuffer = Framebuffer::depth_only(16, 16);
+        let depth = |framebuffer: &Framebuffer, x: usize, y: usize| framebuffer.depth()[y * 16 + x];
+        let max_depths = |renderer: &TileRenderer| -> Vec<f32> {
+            renderer.tiles.iter().map(|tile| tile.max_depth).collect()
+        };
+
+        // covers all four tiles, with small triangles in front and behind in the top left tile
+        let occluder = screen_triangle([(-8.0, -8.0), (48.0, -8.0), (-8.0, 48.0)], 2.0);
+        let small = [(1.0, 1.0), (7.0, 1.0), (1.0, 7.0)];
+        let triangles = [screen_triangle(small, 3.0), screen_triangle(small, 1.0)];
+
+        renderer.render_depth(&mut framebuffer, &config, &[occluder]);
+        assert_eq!(max_depths(&renderer), [2.0; 4]);
+
+        // only the visible triangle is binned once the tile is covered
+        renderer.place_triangles(&triangles);
+        assert_eq!(binned(&renderer), [vec![1], vec![], vec![], vec![]]);
+        renderer
+            .tiles
+            .iter_mut()
+            .for_each(|tile| tile.triangles.clear());
+        renderer.render_depth(&mut framebuffer, &config, &triangles);
+        assert_eq!(depth(&framebuffer, 2, 2), 1.0);
+        assert_eq!(depth(&framebuffer, 12, 12), 2.0);
+
+        // triangles which only partly cover a tile, or may leave holes, don't occlude anything
+        let mut cutout = screen_triangle([(-8.0, -8.0), (48.0, -8.0), (-8.0, 48.0)], 2.0);
+        cutout.alpha_mode = AlphaMode::Cutout(0.5);
+        framebuffer.clear_depth_buffer();
+        renderer.clear_depth();
+        renderer.render_depth(
+            &mut framebuffer,
+            &config,
+            &[screen_triangle(small, 1.0), cutout],
+        );
+        assert_eq!(max_depths(&renderer), [f32::MAX; 4]);
+
+        renderer.place_triangles(&[screen_triangle(small, 3.0)]);
+        assert_eq!(binned(&renderer), [vec![0], vec![], vec![], vec![]]);
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn threaded_binning_keeps_triangle_order() {
+        let config = RenderConfig {
+            tile_width: 8,
+            tile_height: 8,
+            ..test_config()
+        };
+
+        // enough triangles to be split into several chunks, scattered with a simple LCG
+        let mut seed = 1u32;
+        let mut random = |scale: f32| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * scale
+        };
+        let triangles = (0..MIN_BIN_CHUNK * 4)
+            .map(|_| {
+                let (x, y) = (random(WIDTH as f32), random(HEIGHT as f32));
+                let (dx, dy) = (random(24.0), random(24.0));
+                screen_triangle([(x, y), (x + dx, y), (x, y + dy)], 1.0 + random(8.0))
+            })
+            .collect::<Vec<_>>();
+
+        let bin = |threads: usize| {
+            let mut renderer = TileRenderer::unthreaded(&config);
+            renderer.set_threads(threads).unwrap();
+            renderer.update_viewport(WIDTH, HEIGHT, &config);
+            renderer.place_triangles(&triangles);
+            renderer
+                .tiles
+                .iter_mut()
+                .map(|tile| std::mem::take(&mut tile.triangles))
+                .collect::<Vec<_>>()
+        };
+
+        let serial = bin(1);
+        assert!(serial.iter().all(|triangles| !triangles.is_empty()));
+        assert_eq!(bin(4), serial);
+        assert_eq!(bin(3), serial);
+    }
+
+    #[test]
+    fn off_screen_triangles_are_not_binned() {
+        let config = RenderConfig {
+            tile_width: 8,
+            tile_height: 8,
+            ..test_config()
+        };
+        let mut renderer = TileRenderer::unthreaded(&config);
+        renderer.update_viewport(16, 16, &config);
+
+        // beyond each edge of the screen, and far enough away to overflow a tile index
+        let off_screen = [
+            [(-20.0, 2.0), (-4.0, 2.0), (-4.0, 12.0)],
+            [(20.0, 2.0), (36.0, 2.0), (36.0, 12.0)],
+            [(2.0, -20.0), (12.0, -4.0), (2.0, -4.0)],
+            [(2.0, 20.0), (12.0, 36.0), (2.0, 36.0)],
+            [(1e7, 1e7), (2e7, 1e7), (1e7, 2e7)],
+            [(-1e7, -1e7), (-1e7, -2e7), (-2e7, -1e7)],
+        ]
+        .map(|points| screen_triangle(points, 1.0));
+        renderer.place_triangles(&off_screen);
+        assert!(binned(&renderer).iter().all(Vec::is_empty));
+
+        // reaching past the guard band on two sides, and covering the whole screen
+        let huge = screen_triangle(
+            [(-5000.0, -5000.0), (20000.0, -5000.0), (-5000.0, 20000.0)],
+            1.0,
+        );
+        renderer.place_triangles(&[huge]);
+        for tile in renderer.tiles.iter_mut() {
+            assert_eq!(std::mem::take(&mut tile.triangles), [(Cover::Full, 0)]);
+        }
+    }
+
+    #[test]
+    fn triangles_are_clipped_to_guard_band() {
+        // a quad reaching about 10,000 pixels past each edge of the screen, so that every triangle
+        // is clipped by the guard band, or one far to the side of the screen
+        let render_at = |x: f32| {
+            let mut mesh = coloured_quad_mesh();
+            for vertex in mesh.vertices.iter_mut() {
+                vertex.position =
+                    Vec3f::new(vertex.position.x * 1000.0, vertex.position.y * 1000.0, 0.0);
             }
+            let (mut renderer, _) = scene(test_config(), mesh, Vec3f::new(x, 0.0, 3.0));
+
+            render(&mut renderer)
+        };
+
+        // vertex colours barely change across the screen, even after clipping
+        for pixel in render_at(0.0) {
+            assert_near(pixel, quad_colour(0.0, 0.0), 0.01);
+        }
+
+        assert!(render_at(3000.0).iter().all(|&pixel| pixel == CLEAR_COLOUR));
+    }
+
+    #[test]
+    fn discarded_pixels_do_not_occlude() {
+        // quads covering the whole screen, where the nearer one discards its bottom half
+        let quad = || {
+            let mut mesh = unit_quad_mesh();
+            for vertex in mesh.vertices.iter_mut() {
+                vertex.position =
+                    Vec3f::new(vertex.position.x * 10.0, vertex.position.y * 10.0, 0.0);
+            }
+            mesh
+        };
+        let config = RenderConfig {
+            tile_width: 8,
+            tile_height: 8,
+            ..test_config()
+        };
+        let (mut renderer, front) = scene(config, quad(), Vec3f::new(0.0, 0.0, 2.0));
+        let back = renderer.assets_mut().insert_mesh(quad());
+        let shader = renderer.assets_mut().insert_shader(height_shader());
+        renderer.assets_mut().set_mesh_shader(front, Some(shader));
+        renderer
+            .assets_mut()
+            .set_mesh_shading(back, ShadingMode::Flat(RGB::GREEN));
+        renderer
+            .assets_mut()
+            .spawn_mesh_instance(back, &Mat4f::translate(0.0, 0.0, 4.0));
+
+        let pixels = render(&mut renderer);
+
+        assert!(pixel(&pixels, WIDTH / 2, 2).r > 0.0);
+        assert_eq!(pixel(&pixels, WIDTH / 2, 2).g, 0.0);
+        for x in [0, WIDTH / 2, WIDTH - 1] {
+            assert_eq!(pixel(&pixels, x, HEIGHT - 1), RGB::GREEN);
+        }
+    }
+
+    #[test]
+    fn debug_tiles_mark_tile_centres() {
+        // a quad covering the whole screen
+        let mut mesh = unit_quad_mesh();
+        for vertex in mesh.vertices.iter_mut() {
+            vertex.position = Vec3f::new(vertex.position.x * 10.0, vertex.position.y * 10.0, 0.0);
+        }
+        let config = RenderConfig {
+            tile_width: 8,
+            tile_height: 8,
+            debug_tiles: true,
+            ..test_config()
+        };
+        let (mut renderer, quad) = scene(config, mesh, Vec3f::new(0.0, 0.0, 2.0));
+        renderer
+            .assets_mut()
+            .set_mesh_shading(quad, ShadingMode::Flat(RGB::RED));
+
+        let pixels = render(&mut renderer);
+
+        // tiles away from the quad's diagonal, which are each covered by a single triangle
+        for (x, y) in [(4, 20), (WIDTH - 4, 20), (28, 4)] {
+            assert_eq!(pixel(&pixels, x, y), Cover::Full.debug_colour());
+            assert_eq!(pixel(&pixels, x - 1, y), RGB::RED);
+        }
+    }
+
+    #[test]
+    fn varyings_are_interpolated_to_fragment_shader() {
+        let (mut renderer, cube) =
+            scene(test_config(), unit_cube_mesh(), Vec3f::new(0.0, 0.0, 3.0));
+        let shader = renderer.assets_mut().insert_shader(height_shader());
+        renderer.assets_mut().set_mesh_shader(cube, Some(shader));
+        let pixels = render(&mut renderer);
+
+        // the centre of the front face is at zero height, and the top edge at 0.5
+        let top = pixel(&pixels, WIDTH / 2, HEIGHT / 2 - 4);
+        assert!(centre(&pixels).r.abs() < 0.05, "{:?}", centre(&pixels));
+        assert!(top.r > 0.15 && top.r < 0.5, "{top:?}");
+
+        // the bottom half of the cube is discarded
+        assert_eq!(pixel(&pixels, WIDTH / 2, HEIGHT / 2 + 4), CLEAR_COLOUR);
+    }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn threaded_render_matches_single_threaded() {
+        for msaa in [Msaa::Off, Msaa::X4] {
+            let render_with = |threads| {
+                let config = RenderConfig {
+                    threads,
+                    tile_width: 4,
+                    tile_height: 4,
+                    msaa,
+                    ..test_config()
+                };
+                to_bits(&render(&mut blended_scene(config)))
+            };
+
+            assert!(
+                render_with(1) == render_with(4),
+                "renders differ with {msaa:?}"
+            );
         }
     }
+
+    #[test]
+    #[cfg(feature = "multithreaded")]
+    fn thread_count_changes_between_frames() {
+        let config = RenderConfig {
+            threads: 4,
+            tile_width: 4,
+            tile_height: 4,
+            ..test_config()
+        };
+        let mut renderer = blended_scene(config.clone());
+        let threaded = to_bits(&render(&mut renderer));
+
+        renderer
+            .set_config(RenderConfig {
+                threads: 1,
+                ..config.clone()
+            })
+            .unwrap();
+        assert!(
+            to_bits(&render(&mut renderer)) == threaded,
+            "renders differ after switching off threads"
+        );
+
+        renderer
+            .set_config(RenderConfig {
+                threads: 3,
+                ..config
+            })
+            .unwrap();
+        assert!(
+            to_bits(&render(&mut renderer)) == threaded,
+            "renders differ after switching threads back on"
+        );
+    }
 }
@@ -2,16 +2,11 @@ use std::path::{Path, PathBuf};
 
 use maths::{geometry::AABB, linear::Vec3f};
 
-use crate::{FAR, MAP_DEPTH_RANGE, MIP_FACTOR, MIP_LEVELS, NEAR};
+use crate::config::RenderConfig;
 
-/// Map a linear depth value, ranging from [NEAR] to [FAR], to a normalised depth value, ranging from 0.0 to 1.0.
-pub fn normalise_depth(depth: f32) -> f32 {
-    (depth - NEAR) * MAP_DEPTH_RANGE
-}
-
-/// Calculates an appropriate mip level based on the normalised depth and a bias.
-pub fn mip_level(normal_depth: f32, bias: f32) -> usize {
-    (((MIP_FACTOR + bias) * normal_depth) as usize).min(MIP_LEVELS - 1)
+/// Map a linear depth value, ranging from near to far, to a normalised depth value, ranging from 0.0 to 1.0.
+pub fn normalise_depth(depth: f32, config: &RenderConfig) -> f32 {
+    (depth - config.near) * config.map_depth_range()
 }
 
 /// This is used during perspective projection to convert from camera space to screen space.
@@ -32,15 +27,15 @@ pub fn focal_dimensions(
 }
 
 /// Returns an AABB representing the view frustum, based on the given horizontal/vertical field of view.
-pub fn view_frustum_bounds(h_fov_rad: f32, v_fov_rad: f32) -> AABB<Vec3f> {
+pub fn view_frustum_bounds(h_fov_rad: f32, v_fov_rad: f32, config: &RenderConfig) -> AABB<Vec3f> {
     let h_tan = (h_fov_rad * 0.5).tan();
     let v_tan = (v_fov_rad * 0.5).tan();
 
-    let h_opp_far = FAR * h_tan;
-    let v_opp_far = FAR * v_tan;
+    let h_opp_far = config.far * h_tan;
+    let v_opp_far = config.far * v_tan;
 
-    let near_bottom_left = Vec3f::new(-h_opp_far, -v_opp_far, NEAR);
-    let far_top_right = Vec3f::new(h_opp_far, v_opp_far, FAR);
+    let near_bottom_left = Vec3f::new(-h_opp_far, -v_opp_far, config.near);
+    let far_top_right = Vec3f::new(h_opp_far, v_opp_far, config.far);
 
     AABB::new(near_bottom_left, far_top_right)
 }
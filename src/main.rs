@@ -8,7 +8,7 @@ use platform::{
     Event, Input, KeyCode, MouseButton, PixelRenderer, Window, WindowApplication, WindowAttributes,
     WindowSize,
 };
-use renderer::Renderer;
+use renderer::{RenderConfig, Renderer};
 
 struct Demo {
     window: Window<PixelRenderer>,
@@ -29,7 +29,7 @@ impl Demo {
             ..Default::default()
         });
         let input = Input::new();
-        let renderer = Renderer::new(800, 600, 75.0);
+        let renderer = Renderer::new(800, 600, 75.0, RenderConfig::default()).unwrap();
         let player_camera = FreeMovement::new(25.0, 50.0, 33.0, 5.0);
 
         Self {